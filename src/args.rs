@@ -14,6 +14,14 @@ pub struct LazyDotsArgs {
 
     #[clap(long, hide = true)]
     pub completion_shell: Option<String>,
+
+    /// Use this config file instead of $LAZYDOT_CONFIG or the default location.
+    #[clap(long, global = true, value_name = "PATH")]
+    pub config: Option<String>,
+
+    /// Deploy into this folder instead of $HOME. Config and state stay with the current user.
+    #[clap(long, alias = "target", global = true, value_name = "DIR")]
+    pub home: Option<String>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -41,6 +49,48 @@ pub enum Command {
     /// Check the current state of each managed path.
     #[clap(short_flag = 'c')]
     Check(CheckArgs),
+
+    /// List the tracked paths with the settings that apply to each.
+    #[clap(short_flag = 'l')]
+    List(ListArgs),
+
+    /// List dotfolder entries that no tracked path covers.
+    Untracked(UntrackedArgs),
+
+    /// Find untracked links into the dotfolder and delink or remove them.
+    Gc(GcArgs),
+
+    /// Revert the last sync, disable-link, untracked, gc or import stow run.
+    #[clap(short_flag = 'u')]
+    Undo(UndoArgs),
+
+    /// Create a dotfolder and a config pointing to it.
+    Init(InitArgs),
+
+    /// Set up this machine from a cloned dotfolder.
+    Bootstrap(BootstrapArgs),
+
+    /// Copy the tracked dotfiles into a folder or tarball as real files.
+    Export(ExportArgs),
+
+    /// Generate the definition of another dotfile manager linking to the dotfolder.
+    ExportConfig(ExportConfigArgs),
+
+    /// Import dotfiles managed by another tool.
+    #[clap(subcommand)]
+    Import(ImportCommand),
+
+    /// Move the dotfolder and re-point every link to it.
+    Relocate(RelocateArgs),
+
+    /// Inspect or repair the state of the last sync.
+    #[clap(subcommand)]
+    State(StateCommand),
+
+    /// Manage where lazydot finds its config file.
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+
     /// Output shell completion script for a given shell.
     #[clap(short_flag = 'g', hide = true)]
     GenerateCompletion {
@@ -54,6 +104,130 @@ pub struct StatusArgs {}
 #[derive(Debug, Args)]
 pub struct CheckArgs {}
 
+#[derive(Debug, Args)]
+pub struct ListArgs {}
+
+#[derive(Debug, Args)]
+pub struct UndoArgs {}
+
+#[derive(Debug, Args)]
+pub struct UntrackedArgs {}
+
+#[derive(Debug, Args)]
+pub struct GcArgs {
+    /// How many folders below the parent of each tracked path to look into
+    #[clap(long = "depth", default_value_t = crate::dot_manager::DEFAULT_SCAN_DEPTH)]
+    pub depth: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct InitArgs {
+    /// Dotfolder to create
+    #[arg(value_parser, default_value = "~/dotfiles")]
+    pub dotfolder: String,
+
+    /// Also run `git init` in the dotfolder
+    #[clap(long = "git", action)]
+    pub git: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct BootstrapArgs {
+    /// The cloned dotfolder
+    #[arg(value_parser)]
+    pub dotfolder: String,
+
+    /// Sync without asking for confirmation
+    #[clap(long = "yes", short = 'y', action)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Symlink a config file (by default ./.config/lazydot.toml) to the global config location.
+    Link(ConfigLinkArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Folder to create, or a .tar.gz file
+    #[clap(long = "out", short = 'o', value_name = "DIR|FILE.tar.gz")]
+    pub out: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportConfigArgs {
+    /// The dotfile manager to generate a definition for
+    #[clap(long = "format", short = 'f', value_enum)]
+    pub format: ConfigFormat,
+
+    /// Stow package or chezmoi source folder to create, or the .nix file to write
+    #[clap(long = "out", short = 'o', value_name = "PATH")]
+    pub out: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConfigFormat {
+    /// A stow package of relative links into the dotfolder
+    Stow,
+    /// A home-manager module setting `home.file`
+    HomeManager,
+    /// A chezmoi source folder of symlink templates
+    Chezmoi,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ImportCommand {
+    /// Move GNU stow packages into the dotfolder and track their paths.
+    Stow(ImportStowArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ImportStowArgs {
+    /// The stow directory holding the packages
+    #[arg(value_parser)]
+    pub dir: String,
+
+    /// Packages to import, all of them when omitted
+    #[arg(value_parser)]
+    pub packages: Vec<String>,
+
+    /// Only show what would be imported
+    #[clap(long = "dry-run", short = 'n', action)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RelocateArgs {
+    /// New location of the dotfolder
+    #[arg(value_parser)]
+    pub path: String,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum StateCommand {
+    /// Regenerate the state file from the symlinks in home that point into the dotfolder.
+    Rebuild(StateRebuildArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct StateRebuildArgs {
+    /// How many folders below the parent of each tracked path to look into
+    #[clap(long = "depth", default_value_t = crate::dot_manager::DEFAULT_SCAN_DEPTH)]
+    pub depth: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigLinkArgs {
+    /// Config file to link
+    #[arg(value_parser, default_value = ".config/lazydot.toml")]
+    pub path: String,
+
+    /// Replace an existing global config
+    #[clap(long = "force", short = 'f', action)]
+    pub force: bool,
+}
+
 #[derive(Debug, Args)]
 pub struct AddArgs {
     /// Path to add (at least one required)
@@ -1,10 +1,17 @@
-use crate::create_toml_temp::create_default_config;
-use crate::utils::{check_path, delete, expand_path, get_home_dir, get_home_dir_string};
+use crate::create_toml_temp::{DEFAULT_CONFIG, create_default_config};
+use crate::current_state::DeployMode;
+use crate::privileged::{PrivilegedRoot, Privileges};
+use crate::utils::{
+    check_path, delete, expand_env_vars, expand_path, expand_user_path, get_config_dir,
+    get_home_dir, get_home_dir_string, get_hostname, glob_match, has_command, relative_to,
+};
 use serde::{Deserialize, Serialize};
-use std::fs;
 use std::os::unix::fs::symlink;
-use std::path::PathBuf;
-use toml_edit::{Array, DocumentMut, Item, Value};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::{env, fs};
+use toml_edit::de::ValueDeserializer;
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Value};
 
 #[derive(serde::Serialize, Deserialize, Debug)]
 pub struct Config {
@@ -12,9 +19,19 @@ pub struct Config {
 
     // Always treat these paths as unexpanded. Use expand_path() before any real use.
     pub dotfolder_path: String,
-    pub paths: Vec<String>,
+    pub paths: Vec<PathEntry>,
+
+    /// Folders besides home whose paths can be tracked, such as `/` for system files.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<Root>,
+
+    /// The file this config was loaded from, `save()` writes back to it.
+    #[serde(skip)]
+    pub config_file: PathBuf,
 }
 
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Defaults {
     #[serde(default = "default_duplicate_behavior")]
@@ -22,131 +39,749 @@ pub struct Defaults {
 
     #[serde(default = "default_on_delink_behavior")]
     pub on_delink: OnDelinkBehavior,
+
+    #[serde(default)]
+    pub link_style: LinkStyle,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A folder outside home whose paths are stored under `store` in the dotfolder:
+/// `[[roots]] name = "system", target = "/", store = "system/", escalate = "sudo"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Root {
+    pub name: String,
+    pub target: String,
+    pub store: String,
+    /// Command that changes paths in `target` with more privileges, such as `sudo` or `doas`.
+    #[serde(default)]
+    pub escalate: Option<String>,
+}
+
+/// A tracked path. Written as a plain string, or as a table when it carries per-path settings:
+/// `{ path = "~/.ssh/config", on_duplicate = "skip", mode = "copy", note = "..." }`.
+/// Settings left out fall back to `[defaults]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawPathEntry", into = "RawPathEntry")]
+pub struct PathEntry {
+    pub path: String,
+    pub link_style: Option<LinkStyle>,
+    pub mode: Option<PathMode>,
+    pub granularity: Option<Granularity>,
+    /// Patterns left out of a `granularity = "files"` folder, on top of `.lazydotignore`.
+    pub ignore: Option<Vec<String>>,
+    pub on_duplicate: Option<DuplicateBehavior>,
+    pub on_delink: Option<OnDelinkBehavior>,
+    pub when: Option<Condition>,
+    /// Free text for whoever reads the config, shown by `lazydot list`.
+    pub note: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawPathEntry {
+    Plain(String),
+    Detailed {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        link_style: Option<LinkStyle>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<PathMode>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        granularity: Option<Granularity>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ignore: Option<Vec<String>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        on_duplicate: Option<DuplicateBehavior>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        on_delink: Option<OnDelinkBehavior>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        when: Option<Condition>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
+    },
+}
+
+impl From<RawPathEntry> for PathEntry {
+    fn from(raw: RawPathEntry) -> Self {
+        match raw {
+            RawPathEntry::Plain(path) => PathEntry::new(path),
+            RawPathEntry::Detailed {
+                path,
+                link_style,
+                mode,
+                granularity,
+                ignore,
+                on_duplicate,
+                on_delink,
+                when,
+                note,
+            } => PathEntry {
+                path,
+                link_style,
+                mode,
+                granularity,
+                ignore,
+                on_duplicate,
+                on_delink,
+                when,
+                note,
+            },
+        }
+    }
+}
+
+impl From<PathEntry> for RawPathEntry {
+    fn from(entry: PathEntry) -> Self {
+        if entry.is_plain() {
+            return RawPathEntry::Plain(entry.path);
+        }
+        RawPathEntry::Detailed {
+            path: entry.path,
+            link_style: entry.link_style,
+            mode: entry.mode,
+            granularity: entry.granularity,
+            ignore: entry.ignore,
+            on_duplicate: entry.on_duplicate,
+            on_delink: entry.on_delink,
+            when: entry.when,
+            note: entry.note,
+        }
+    }
+}
+
+impl PathEntry {
+    pub fn new(path: String) -> PathEntry {
+        PathEntry {
+            path,
+            link_style: None,
+            mode: None,
+            granularity: None,
+            ignore: None,
+            on_duplicate: None,
+            on_delink: None,
+            when: None,
+            note: None,
+        }
+    }
+
+    /// True when the entry has no per-path settings and can be written as a plain string.
+    pub fn is_plain(&self) -> bool {
+        self.link_style.is_none()
+            && self.mode.is_none()
+            && self.granularity.is_none()
+            && self.ignore.is_none()
+            && self.on_duplicate.is_none()
+            && self.on_delink.is_none()
+            && self.when.is_none()
+            && self.note.is_none()
+    }
+
+    /// Why the entry doesn't apply on this machine, `None` when it does.
+    pub fn excluded_because(&self) -> Option<String> {
+        self.when.as_ref().and_then(Condition::unmet)
+    }
+
+    fn to_toml(&self) -> Value {
+        if self.is_plain() {
+            return Value::from(self.path.as_str());
+        }
+        let mut table = InlineTable::new();
+        table.insert("path", Value::from(self.path.as_str()));
+        if let Some(link_style) = &self.link_style {
+            table.insert("link_style", Value::from(link_style.as_str()));
+        }
+        if let Some(mode) = &self.mode {
+            table.insert("mode", Value::from(mode.as_str()));
+        }
+        if let Some(granularity) = &self.granularity {
+            table.insert("granularity", Value::from(granularity.as_str()));
+        }
+        if let Some(ignore) = &self.ignore {
+            table.insert("ignore", Value::Array(ignore.iter().collect()));
+        }
+        if let Some(on_duplicate) = &self.on_duplicate {
+            table.insert("on_duplicate", Value::from(on_duplicate.as_str()));
+        }
+        if let Some(on_delink) = &self.on_delink {
+            table.insert("on_delink", Value::from(on_delink.as_str()));
+        }
+        if let Some(when) = &self.when {
+            table.insert("when", Value::InlineTable(when.to_toml()));
+        }
+        if let Some(note) = &self.note {
+            table.insert("note", Value::from(note.as_str()));
+        }
+        Value::InlineTable(table)
+    }
+
+    /// Reads back an entry of the `paths` array of the config document.
+    fn from_toml(value: &Value) -> Option<PathEntry> {
+        let mut value = value.clone();
+        value.decor_mut().clear();
+        let deserializer = value.to_string().parse::<ValueDeserializer>().ok()?;
+        PathEntry::deserialize(deserializer).ok()
+    }
+}
+
+/// Conditions a path is only linked under, all of which must hold:
+/// `when = { os = "linux", hostname = "work-*", env = "WAYLAND_DISPLAY", has_command = "nvim" }`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    /// `linux`, `macos`, ... as a glob.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    /// Glob matched against the hostname.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// A variable that must be set and not empty, or `VAR=glob` to also match its value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    /// A command that must be found in `$PATH`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_command: Option<String>,
+}
+
+impl Condition {
+    /// The first condition that doesn't hold on this machine, as a reason to show.
+    pub fn unmet(&self) -> Option<String> {
+        if let Some(os) = &self.os
+            && !glob_match(os, env::consts::OS)
+        {
+            return Some(format!("os is {}, not {}", env::consts::OS, os));
+        }
+        if let Some(hostname) = &self.hostname {
+            let current = get_hostname();
+            if !glob_match(hostname, &current) {
+                return Some(format!("hostname {} doesn't match {}", current, hostname));
+            }
+        }
+        if let Some(var) = &self.env {
+            let (name, pattern) = match var.split_once('=') {
+                Some((name, pattern)) => (name, Some(pattern)),
+                None => (var.as_str(), None),
+            };
+            match (
+                env::var(name).ok().filter(|value| !value.is_empty()),
+                pattern,
+            ) {
+                (None, _) => return Some(format!("${} is not set", name)),
+                (Some(value), Some(pattern)) if !glob_match(pattern, &value) => {
+                    return Some(format!("${} is {}, not {}", name, value, pattern));
+                }
+                _ => {}
+            }
+        }
+        if let Some(command) = &self.has_command
+            && !has_command(command)
+        {
+            return Some(format!("{} is not installed", command));
+        }
+        None
+    }
+
+    fn to_toml(&self) -> InlineTable {
+        let mut table = InlineTable::new();
+        for (key, value) in [
+            ("os", &self.os),
+            ("hostname", &self.hostname),
+            ("env", &self.env),
+            ("has_command", &self.has_command),
+        ] {
+            if let Some(value) = value {
+                table.insert(key, Value::from(value.as_str()));
+            }
+        }
+        table
+    }
+}
+
+impl PartialEq<&str> for PathEntry {
+    fn eq(&self, other: &&str) -> bool {
+        self.path == *other
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum DuplicateBehavior {
     Ask,
     OverwriteHome,
     OverwriteDotfile,
     BackupHome,
+    /// Keeps the home copy, the dotfolder copy is renamed with a `.bak` extension first.
+    BackupDotfile,
+    /// Keeps the side modified last and backs up the other one.
+    Newest,
+    /// Only settles copies with the same content, asks about the others.
+    IdenticalOnly,
+    /// Combines two copies of a folder file by file, asking only about files that differ.
+    /// Other duplicates are asked about.
+    Merge,
     Skip,
 }
+
+impl DuplicateBehavior {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DuplicateBehavior::Ask => "ask",
+            DuplicateBehavior::OverwriteHome => "overwritehome",
+            DuplicateBehavior::OverwriteDotfile => "overwritedotfile",
+            DuplicateBehavior::BackupHome => "backuphome",
+            DuplicateBehavior::BackupDotfile => "backupdotfile",
+            DuplicateBehavior::Newest => "newest",
+            DuplicateBehavior::IdenticalOnly => "identicalonly",
+            DuplicateBehavior::Merge => "merge",
+            DuplicateBehavior::Skip => "skip",
+        }
+    }
+}
 fn default_duplicate_behavior() -> DuplicateBehavior {
     DuplicateBehavior::Ask
 }
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum OnDelinkBehavior {
     Remove,
     Keep,
 }
+
+impl OnDelinkBehavior {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OnDelinkBehavior::Remove => "remove",
+            OnDelinkBehavior::Keep => "keep",
+        }
+    }
+}
 fn default_on_delink_behavior() -> OnDelinkBehavior {
     OnDelinkBehavior::Remove
 }
 
+/// How the symlink in home refers to the dotfolder copy.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// `~/.bashrc -> /home/user/dotfiles/.bashrc`
+    #[default]
+    Absolute,
+    /// `~/.bashrc -> dotfiles/.bashrc`, survives home being mounted at another path.
+    Relative,
+}
+
+/// How a tracked path is put in home.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathMode {
+    /// A symlink to the dotfolder copy.
+    #[default]
+    Link,
+    /// A real copy, for programs that replace or refuse symlinks. Changes made in home show up
+    /// as a duplicate on the next sync.
+    Copy,
+}
+
+impl PathMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PathMode::Link => "link",
+            PathMode::Copy => "copy",
+        }
+    }
+}
+
+/// What a tracked folder is linked as.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    /// A single symlink to the dotfolder copy.
+    #[default]
+    Whole,
+    /// A real folder in home holding a symlink per file, so programs can keep writing their
+    /// own files next to the tracked ones.
+    Files,
+}
+
+impl Granularity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Whole => "whole",
+            Granularity::Files => "files",
+        }
+    }
+}
+
+impl LinkStyle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkStyle::Absolute => "absolute",
+            LinkStyle::Relative => "relative",
+        }
+    }
+}
+
 impl Config {
     pub fn new() -> Config {
-        let global_config_path = get_home_dir().join(".config/lazydot.toml");
-        let local_config_path = expand_path(".config/lazydot.toml");
-        let case_checked = (global_config_path.exists(), local_config_path.exists());
-        let config_file: PathBuf;
-        if case_checked.0 {
-            config_file = global_config_path;
-        } else if case_checked.1 {
-            if global_config_path.is_symlink() {
-                delete(&global_config_path);
-            }
-            symlink(&local_config_path, &global_config_path)
-                .expect("Failed to symlink the local config file with the global config file");
-            config_file = local_config_path;
-        } else {
-            create_default_config(&global_config_path);
-            config_file = global_config_path
-        }
+        Self::load(&Self::locate())
+    }
 
-        let content = fs::read_to_string(&config_file).expect("Unable to read config file");
+    /// Loads `config_file`, or the default config when it doesn't exist yet.
+    /// Nothing is written until `save()` is called.
+    pub fn load(config_file: &Path) -> Config {
+        let content = if config_file.exists() {
+            fs::read_to_string(config_file).expect("Unable to read config file")
+        } else {
+            DEFAULT_CONFIG.to_string()
+        };
 
-        let config: Config = toml::from_str(&content).expect("Failed to parse lazydot.toml");
+        let mut config: Config = toml::from_str(&content).expect("Failed to parse lazydot.toml");
+        config.config_file = config_file.to_path_buf();
 
         config.validate_config();
 
         config
     }
 
+    /// Uses `path` as the config file for the rest of the run, as given by `--config`.
+    pub fn set_config_path(path: PathBuf) {
+        CONFIG_PATH
+            .set(path)
+            .expect("The config path can only be set once");
+    }
+
+    /// Finds the config file to use, in order: `--config`, `$LAZYDOT_CONFIG`,
+    /// `$XDG_CONFIG_HOME/lazydot.toml` (or `~/.config/lazydot.toml`), then `./.config/lazydot.toml`.
+    /// Only looks, never creates or links anything.
+    fn locate() -> PathBuf {
+        let explicit_config_path = CONFIG_PATH.get().cloned().or_else(|| {
+            env::var("LAZYDOT_CONFIG")
+                .ok()
+                .filter(|path| !path.is_empty())
+                .map(|path| expand_user_path(&path))
+        });
+        if let Some(config_file) = explicit_config_path {
+            return config_file;
+        }
+
+        let global_config_path = Self::global_config_path();
+        let local_config_path = expand_path(".config/lazydot.toml");
+        if !global_config_path.exists() && local_config_path.exists() {
+            return local_config_path;
+        }
+        global_config_path
+    }
+
+    pub fn global_config_path() -> PathBuf {
+        get_config_dir().join("lazydot.toml")
+    }
+
+    /// Symlinks `local_config` to the global config location, as done by `lazydot config link`.
+    /// An existing global config is only replaced with `force`.
+    pub fn link_global(local_config: &Path, force: bool) -> Result<PathBuf, String> {
+        if !local_config.is_file() {
+            return Err(format!("{} is not a config file", local_config.display()));
+        }
+        let local_config = local_config
+            .canonicalize()
+            .map_err(|e| format!("Failed to resolve {}: {}", local_config.display(), e))?;
+        let global_config_path = Self::global_config_path();
+
+        if global_config_path.canonicalize().ok() == Some(local_config.clone()) {
+            return Ok(global_config_path);
+        }
+        if global_config_path.exists() || global_config_path.is_symlink() {
+            if !force {
+                return Err(format!(
+                    "{} already exists, use --force to replace it",
+                    global_config_path.display()
+                ));
+            }
+            delete(&global_config_path);
+        }
+
+        let parent = global_config_path
+            .parent()
+            .expect("Failed to get the config folder");
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        symlink(&local_config, &global_config_path).map_err(|e| e.to_string())?;
+        Ok(global_config_path)
+    }
+
     pub fn save(&self) {
         self.validate_config();
 
-        let config_file = get_home_dir().join(".config/lazydot.toml");
+        let config_file = &self.config_file;
         if !config_file.exists() {
             eprintln!(
                 "Config file does not exist. Creating a new one at {}",
                 config_file.display()
             );
-            create_default_config(&config_file);
+            create_default_config(config_file);
         }
         let content =
-            fs::read_to_string(&config_file).expect("Failed to read the config for update");
+            fs::read_to_string(config_file).expect("Failed to read the config for update");
 
         let mut doc = content
             .parse::<DocumentMut>()
             .expect("Failed to parse config as TOML document");
 
-        doc["dotfolder_path"] = toml_edit::value(&self.dotfolder_path);
+        set_value(
+            &mut doc["dotfolder_path"],
+            Value::from(&self.dotfolder_path),
+        );
 
-        // Manually construct the array for paths
-        let mut paths_array = Array::default();
-        for path in &self.paths {
-            paths_array.push(path.as_str());
+        let paths = self.paths_to_toml(doc.get("paths").and_then(Item::as_array));
+        set_value(&mut doc["paths"], Value::Array(paths));
+
+        set_value(
+            &mut doc["defaults"]["on_duplicate"],
+            Value::from(self.defaults.on_duplicate.as_str()),
+        );
+        set_value(
+            &mut doc["defaults"]["on_delink"],
+            Value::from(self.defaults.on_delink.as_str()),
+        );
+        // Configs that never chose a link style are left without one
+        let has_link_style = doc
+            .get("defaults")
+            .and_then(|defaults| defaults.get("link_style"))
+            .is_some();
+        if has_link_style || self.defaults.link_style != LinkStyle::default() {
+            set_value(
+                &mut doc["defaults"]["link_style"],
+                Value::from(self.defaults.link_style.as_str()),
+            );
         }
-        doc["paths"] = Item::Value(Value::Array(paths_array));
 
-        doc["defaults"]["on_duplicate"] =
-            toml_edit::value(format!("{:?}", self.defaults.on_duplicate).to_lowercase());
+        fs::write(config_file, doc.to_string()).expect("Failed to write updated config");
+    }
 
-        doc["defaults"]["on_delink"] =
-            toml_edit::value(format!("{:?}", self.defaults.on_delink).to_lowercase());
+    /// The `paths` array to save. Entries that didn't change are kept as written, with their
+    /// comments, and changed ones keep the comments around them.
+    fn paths_to_toml(&self, existing: Option<&Array>) -> Array {
+        let existing_values: Vec<(PathEntry, &Value)> = existing
+            .into_iter()
+            .flat_map(Array::iter)
+            .filter_map(|value| Some((PathEntry::from_toml(value)?, value)))
+            .collect();
+        // New entries go on their own line in an array written one entry per line
+        let multiline = existing_values
+            .iter()
+            .any(|(_, value)| decor_prefix(value).contains('\n'));
 
-        fs::write(config_file, doc.to_string()).expect("Failed to write updated config");
+        // A comment at the end of an entry's line is stored before the next entry, or in the
+        // array's trailing for the last one. It stays with the entry it follows.
+        let trailing = existing
+            .and_then(|array| array.trailing().as_str())
+            .unwrap_or_default();
+        let line_ends: Vec<&str> = existing_values
+            .iter()
+            .skip(1)
+            .map(|(_, value)| split_line_end(decor_prefix(value)).0)
+            .chain([split_line_end(trailing).0])
+            .collect();
+        let mut line_end = existing_values
+            .first()
+            .map(|(_, value)| split_line_end(decor_prefix(value)).0)
+            .unwrap_or_default();
+
+        let mut array = Array::new();
+        for entry in &self.paths {
+            let old = existing_values
+                .iter()
+                .position(|(old, _)| old.path == entry.path);
+            let (mut value, leading, own_line_end) = match old {
+                Some(i) => {
+                    let (old, old_value) = &existing_values[i];
+                    let value = match old == entry {
+                        true => (*old_value).clone(),
+                        false => {
+                            let mut value = entry.to_toml();
+                            *value.decor_mut() = old_value.decor().clone();
+                            value
+                        }
+                    };
+                    let leading = split_line_end(decor_prefix(old_value)).1;
+                    (value, leading, line_ends[i])
+                }
+                None if multiline => (entry.to_toml(), "\n    ", ""),
+                None => (entry.to_toml(), " ", ""),
+            };
+            value
+                .decor_mut()
+                .set_prefix(format!("{}{}", line_end, leading));
+            line_end = own_line_end;
+            array.push_formatted(value);
+        }
+        match existing {
+            Some(existing) if multiline => {
+                array.set_trailing(format!("{}{}", line_end, split_line_end(trailing).1));
+                array.set_trailing_comma(existing.trailing_comma());
+            }
+            _ => array.fmt(),
+        }
+        array
     }
 
-    fn restrict_to_home(&mut self, path: String) -> Result<String, String> {
-        let mut path = check_path(&path)?;
-        if path.starts_with(&self.dotfolder_path) {
-            let relative_path = path
-                .strip_prefix(&self.dotfolder_path)
-                .expect("Failed to strip prefix");
-            path = get_home_dir()
-                .join(relative_path)
-                .to_str()
-                .ok_or("Failed to convert home directory path to string")?
-                .to_string();
+    pub(crate) fn restrict_to_home(&self, path: String) -> Result<String, String> {
+        // Paths given inside the dotfolder refer to their place in home or in a root
+        let path_buf = expand_path(&path);
+        let dotfolder = expand_user_path(&self.dotfolder_path);
+        match relative_to(&path_buf, &dotfolder) {
+            Some(relative) if relative.as_os_str().is_empty() => {
+                Err(format!("path {} is the dotfolder", path))
+            }
+            Some(_) if !path_buf.exists() => Err(format!("path {} does not exist", path)),
+            Some(relative) => Ok(self
+                .roots
+                .iter()
+                .find_map(|root| {
+                    let in_root = relative.strip_prefix(&root.store).ok()?;
+                    (!in_root.as_os_str().is_empty()).then(|| {
+                        expand_path(&root.target)
+                            .join(in_root)
+                            .display()
+                            .to_string()
+                    })
+                })
+                .unwrap_or(format!("~/{}", relative.display()))),
+            None if self.root_for(&path_buf).is_some() => {
+                if !path_buf.exists() {
+                    return Err(format!("path {} does not exist", path));
+                }
+                Ok(path_buf.display().to_string())
+            }
+            None => check_path(&path),
         }
-        Ok(path)
     }
     pub fn add_path(&mut self, path: String) -> Result<(), String> {
-        let path = self.restrict_to_home(path)?;
-        if self.paths.contains(&path) {
+        expand_env_vars(&path).map_err(|e| format!("path {}: {}", path, e))?;
+        let restricted = self.restrict_to_home(path.clone())?;
+        // Paths written with variables are kept as written, so the config stays portable
+        let path = if path.contains('$') && expand_path(&path) == expand_path(&restricted) {
+            path
+        } else {
+            restricted
+        };
+        if self.is_tracked(&path) {
             return Ok(());
         }
-        self.paths.push(path);
+        self.paths.push(PathEntry::new(path));
         self.save();
         Ok(())
     }
 
-    pub fn remove_path(&mut self, path: String) {
-        let path = self.restrict_to_home(path).unwrap();
+    pub fn remove_path(&mut self, path: String) -> Result<(), String> {
+        // A tracked path may already be gone from home
+        let path = match self.is_tracked(&path) {
+            true => expand_path(&path),
+            false => expand_path(&self.restrict_to_home(path)?),
+        };
         for (i, v) in self.paths.iter().enumerate() {
-            if *v == path {
+            if expand_path(&v.path) == path {
                 self.paths.remove(i);
                 self.save();
-                return;
+                return Ok(());
             }
         }
+        Ok(())
     }
+    /// The entries whose `when` conditions hold on this machine.
+    pub fn active_entries(&self) -> impl Iterator<Item = &PathEntry> {
+        self.paths
+            .iter()
+            .filter(|entry| entry.excluded_because().is_none())
+    }
+
+    /// The entries left out on this machine, with the reason.
+    pub fn excluded_entries(&self) -> Vec<(&PathEntry, String)> {
+        self.paths
+            .iter()
+            .filter_map(|entry| Some((entry, entry.excluded_because()?)))
+            .collect()
+    }
+
+    /// The tracked paths that apply on this machine, in their unexpanded form.
+    pub fn tracked_paths(&self) -> Vec<String> {
+        self.active_entries()
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+
+    /// True when an entry expands to the same path as `path`, however it is written.
+    pub fn is_tracked(&self, path: &str) -> bool {
+        self.entry_for(&expand_path(path)).is_some()
+    }
+
+    /// The entry expanding to `path`, however it is written.
+    pub fn entry_for(&self, path: &Path) -> Option<&PathEntry> {
+        self.paths
+            .iter()
+            .find(|entry| expand_path(&entry.path) == path)
+    }
+
+    /// The root holding `path` and the path relative to its target. Home takes precedence,
+    /// so paths in home never belong to a root.
+    pub fn root_for(&self, path: &Path) -> Option<(&Root, PathBuf)> {
+        if relative_to(path, &get_home_dir()).is_some() {
+            return None;
+        }
+        self.roots
+            .iter()
+            .filter_map(|root| Some((root, relative_to(path, &expand_path(&root.target))?)))
+            .filter(|(_, relative)| !relative.as_os_str().is_empty())
+            .max_by_key(|(root, _)| expand_path(&root.target).components().count())
+    }
+
+    /// Roots that need `escalate` to be changed, except for home and the dotfolder.
+    pub fn privileges(&self) -> Privileges {
+        Privileges {
+            roots: self
+                .roots
+                .iter()
+                .filter_map(|root| {
+                    Some(PrivilegedRoot {
+                        target: expand_path(&root.target),
+                        command: root.escalate.clone()?,
+                    })
+                })
+                .collect(),
+            except: vec![get_home_dir(), expand_user_path(&self.dotfolder_path)],
+        }
+    }
+
+    pub fn link_style_for(&self, entry: &PathEntry) -> LinkStyle {
+        entry.link_style.unwrap_or(self.defaults.link_style)
+    }
+
+    pub fn on_duplicate_for(&self, entry: &PathEntry) -> DuplicateBehavior {
+        entry.on_duplicate.unwrap_or(self.defaults.on_duplicate)
+    }
+
+    pub fn on_delink_for(&self, entry: &PathEntry) -> OnDelinkBehavior {
+        entry.on_delink.unwrap_or(self.defaults.on_delink)
+    }
+
+    /// How `entry` is deployed: a copy, a link per file, or a link in its link style.
+    pub fn deploy_mode_for(&self, entry: &PathEntry) -> DeployMode {
+        if entry.granularity == Some(Granularity::Files) {
+            return DeployMode::FileLinks;
+        }
+        match entry.mode.unwrap_or_default() {
+            PathMode::Copy => DeployMode::Copy,
+            PathMode::Link => self.link_style_for(entry).into(),
+        }
+    }
+
     fn validate_config(&self) {
-        for path in &self.paths {
+        for entry in &self.paths {
+            let path = &expand_env_vars(&entry.path)
+                .unwrap_or_else(|e| panic!("Invalid path: \"{}\" {}.", entry.path, e));
+            if entry.mode == Some(PathMode::Copy) && entry.granularity == Some(Granularity::Files) {
+                panic!(
+                    "Invalid path: \"{}\" mode = \"copy\" can't be combined with granularity = \"files\".",
+                    entry.path
+                );
+            }
             if path.starts_with("~/") {
                 continue;
             }
@@ -157,19 +792,59 @@ impl Config {
                     path.display()
                 );
             }
-            if !path.starts_with(get_home_dir_string()) {
+            if !path.starts_with(get_home_dir_string()) && self.root_for(&path).is_none() {
                 panic!(
-                    "Invalid path: \"{}\" paths should be in the home directory.",
+                    "Invalid path: \"{}\" paths should be in the home directory or a root.",
                     path.display()
                 );
             }
         }
 
-        if !self.dotfolder_path.starts_with("~/") {
+        for root in &self.roots {
+            if !root.target.starts_with("~/") && PathBuf::from(&root.target).is_relative() {
+                panic!(
+                    "Invalid root: \"{}\" the target should start with ~/ or be absolute.",
+                    root.name
+                );
+            }
+            if PathBuf::from(&root.store).is_absolute() || root.store.trim_matches('/').is_empty() {
+                panic!(
+                    "Invalid root: \"{}\" the store should be a folder inside the dotfolder.",
+                    root.name
+                );
+            }
+        }
+
+        let dotfolder_path = expand_env_vars(&self.dotfolder_path)
+            .unwrap_or_else(|e| panic!("Invalid path: \"{}\" {}.", self.dotfolder_path, e));
+        if !dotfolder_path.starts_with("~/") && PathBuf::from(&dotfolder_path).is_relative() {
             panic!(
-                "Invalid path: \"{}\" the dotfolder path should be in the home directory.",
+                "Invalid path: \"{}\" the dotfolder path should start with ~/ or be absolute.",
                 self.dotfolder_path
             );
         }
     }
 }
+
+/// Replaces the value of `item`, keeping the comment that follows it on the same line.
+fn decor_prefix(value: &Value) -> &str {
+    value
+        .decor()
+        .prefix()
+        .and_then(|prefix| prefix.as_str())
+        .unwrap_or_default()
+}
+
+/// Splits the whitespace and comments before an array entry at the first line break, into
+/// the end of the line before and what leads up to the entry.
+fn split_line_end(decor: &str) -> (&str, &str) {
+    decor.split_at(decor.find('\n').unwrap_or(decor.len()))
+}
+
+fn set_value(item: &mut Item, value: Value) {
+    let decor = item.as_value().map(|old| old.decor().clone());
+    *item = Item::Value(value);
+    if let (Some(decor), Some(value)) = (decor, item.as_value_mut()) {
+        *value.decor_mut() = decor;
+    }
+}
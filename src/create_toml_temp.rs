@@ -1,9 +1,9 @@
-use std::path::PathBuf;
+use std::path::Path;
 
-const DEFAULT_CONFIG: &str = r#"
+pub const DEFAULT_CONFIG: &str = r#"
 # Lazydot Configuration File
 
-# Path to the dotfiles folder (must start with ~/)
+# Path to the dotfiles folder (starts with ~/, or absolute for a folder outside home)
 dotfolder_path = "~/mydotfolder"
 
 # List of dotfile paths to manage (each must start with ~/ or /)
@@ -12,6 +12,10 @@ dotfolder_path = "~/mydotfolder"
 #     "~/example/.bashrc",
 #     "~/example/.vimrc",
 # ]
+# An entry can also be a table with settings of its own:
+#     { path = "~/.ssh/config", mode = "copy", on_duplicate = "skip", note = "work keys" },
+# A folder programs also write to can be linked file by file, leaving out what they generate:
+#     { path = "~/.config/fish", granularity = "files", ignore = ["fish_variables"] },
 paths = [
     "~/.config/lazydot.toml",
 ]
@@ -22,15 +26,30 @@ paths = [
 # - overwritehome: Overwrite the file in HOME with the dotfolder version
 # - overwritedotfile: Overwrite the dotfolder copy with the HOME version
 # - backuphome: Backup the HOME file before overwriting
+# - backupdotfile: Backup the dotfolder copy before replacing it with the HOME file
+# - newest: Keep whichever copy was modified last, backing up the other
+# - identicalonly: Link copies with the same content, ask about the others
+# - merge: Combine both copies of a folder file by file, ask only about files that differ
 # - skip: Do nothing and skip the conflict
+# Copies with the same content are linked without asking, unless set to skip.
 on_duplicate = "ask"
 
 # Behavior after a link is disabled (delinked):
 # - remove: Remove the file from the dotfolder after restoring it to HOME (default)
 # - keep: Keep the file in the dotfolder even after restoring it to HOME
+# A single path can override it, as on_duplicate: { path = "~/.local/share/app", on_delink = "keep" }
 on_delink = "remove"
+
+# How symlinks in HOME point to the dotfolder:
+# - absolute: Link to the full dotfolder path (default)
+# - relative: Link relative to the symlink location, survives HOME being mounted elsewhere
+# A single path can override it: { path = "~/.bashrc", link_style = "relative" }
+link_style = "absolute"
 "#;
 
-pub fn create_default_config(config_file: &PathBuf) {
+pub fn create_default_config(config_file: &Path) {
+    if let Some(parent) = config_file.parent() {
+        std::fs::create_dir_all(parent).expect("Failed to create the config folder");
+    }
     std::fs::write(config_file, DEFAULT_CONFIG).expect("Failed to create default config");
 }
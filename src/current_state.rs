@@ -1,39 +1,261 @@
-use crate::config::Config;
-use crate::utils::expand_path;
+use crate::config::{Config, LinkStyle, OnDelinkBehavior};
+use crate::utils::{
+    delete, expand_path, expand_user_path, get_home_dir, get_hostname, get_state_dir,
+    get_user_home_dir, hash_path,
+};
+use ansi_term::Colour::Yellow;
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+const STATE_FILE: &str = "current_state.toml";
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct CurrentState {
-    pub paths: Vec<String>,
+    pub paths: Vec<PathRecord>,
 }
 
-impl CurrentState {
-    pub fn save(&self, config: &Config) {
-        let new_current_state = CurrentState {
-            paths: config.paths.clone(),
+/// What the last sync actually did with a tracked path.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawPathRecord")]
+pub struct PathRecord {
+    pub path: String,
+    pub outcome: Outcome,
+    /// Target written in the symlink in home.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<DeployMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<FileKind>,
+    /// Content hash of the dotfolder copy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// The path's own `on_delink`, still applied once it is removed from the config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_delink: Option<OnDelinkBehavior>,
+    /// Unix time of the sync that wrote this record.
+    #[serde(default)]
+    pub deployed_at: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawPathRecord {
+    /// Older versions only listed the paths of the config.
+    Legacy(String),
+    Record {
+        path: String,
+        outcome: Outcome,
+        #[serde(default)]
+        target: Option<PathBuf>,
+        #[serde(default)]
+        mode: Option<DeployMode>,
+        #[serde(default)]
+        kind: Option<FileKind>,
+        #[serde(default)]
+        hash: Option<String>,
+        #[serde(default)]
+        on_delink: Option<OnDelinkBehavior>,
+        #[serde(default)]
+        deployed_at: u64,
+    },
+}
+
+impl From<RawPathRecord> for PathRecord {
+    fn from(raw: RawPathRecord) -> Self {
+        match raw {
+            RawPathRecord::Legacy(path) => PathRecord {
+                path,
+                outcome: Outcome::Linked,
+                target: None,
+                mode: None,
+                kind: None,
+                hash: None,
+                on_delink: None,
+                deployed_at: 0,
+            },
+            RawPathRecord::Record {
+                path,
+                outcome,
+                target,
+                mode,
+                kind,
+                hash,
+                on_delink,
+                deployed_at,
+            } => PathRecord {
+                path,
+                outcome,
+                target,
+                mode,
+                kind,
+                hash,
+                on_delink,
+                deployed_at,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Linked,
+    /// Left alone because of `on_duplicate = "skip"`.
+    Skipped,
+    /// Creating the link failed.
+    Failed,
+    /// Neither in home nor in the dotfolder.
+    Missing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeployMode {
+    AbsoluteLink,
+    RelativeLink,
+    /// A real copy, for `mode = "copy"` paths.
+    Copy,
+    /// A real folder holding a link per file, for `granularity = "files"` paths.
+    FileLinks,
+}
+
+impl From<LinkStyle> for DeployMode {
+    fn from(style: LinkStyle) -> Self {
+        match style {
+            LinkStyle::Absolute => DeployMode::AbsoluteLink,
+            LinkStyle::Relative => DeployMode::RelativeLink,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    File,
+    Dir,
+}
+
+impl PathRecord {
+    /// Records the outcome of syncing `path`, reading the rest from the filesystem.
+    pub fn new(
+        path: &str,
+        path_in_home: &Path,
+        path_in_dotfolder: &Path,
+        mode: DeployMode,
+        outcome: Outcome,
+    ) -> PathRecord {
+        let kind = if path_in_dotfolder.is_dir() {
+            Some(FileKind::Dir)
+        } else if path_in_dotfolder.is_file() {
+            Some(FileKind::File)
+        } else {
+            None
         };
-        let dotfolder = expand_path(&config.dotfolder_path);
-        let path = dotfolder.join("current_state.toml");
+        PathRecord {
+            path: path.to_string(),
+            outcome,
+            target: match outcome {
+                Outcome::Linked => fs::read_link(path_in_home).ok(),
+                _ => None,
+            },
+            mode: Some(mode),
+            kind,
+            hash: hash_path(path_in_dotfolder),
+            on_delink: None,
+            deployed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl CurrentState {
+    /// Paths the last sync really linked.
+    pub fn linked_paths(&self) -> Vec<String> {
+        self.paths
+            .iter()
+            .filter(|record| record.outcome == Outcome::Linked)
+            .map(|record| record.path.clone())
+            .collect()
+    }
 
-        let toml_string =
-            toml::to_string_pretty(&new_current_state).expect("Failed to serialize current state");
-        fs::write(path, toml_string).expect("Failed to write current state file");
+    pub fn get(&self, path: &str) -> Option<&PathRecord> {
+        self.paths.iter().find(|record| record.path == path)
     }
 
-    pub fn new(config: &Config) -> Self {
-        let dotfolder = expand_path(&config.dotfolder_path);
-        let path = dotfolder.join("current_state.toml");
+    /// The record of the path expanding to `path`, however it was written in the config.
+    pub fn find(&self, path: &Path) -> Option<&PathRecord> {
+        self.paths
+            .iter()
+            .find(|record| expand_path(&record.path) == path)
+    }
+
+    pub fn write(&self, config: &Config) {
+        let dir = Self::dir(config);
+        fs::create_dir_all(&dir).expect("Failed to create the state folder");
+
+        let toml_string = toml::to_string_pretty(self).expect("Failed to serialize current state");
+        fs::write(dir.join(STATE_FILE), toml_string).expect("Failed to write current state file");
+        Self::remove_legacy_state(config, &dir.join(STATE_FILE));
+    }
+
+    /// Loads the state of the last sync, failing when the state file can't be parsed.
+    pub fn new(config: &Config) -> Result<Self, String> {
+        let mut path = Self::dir(config).join(STATE_FILE);
+        let legacy_path = Self::legacy_path(config);
+        if !path.exists() && legacy_path.is_file() {
+            path = legacy_path;
+        }
 
         if !path.exists() {
-            // eprintln!(
-            //     "Warning: current state file does not exist at '{}', assuming empty state.",
-            //     path.display()
-            // );
-            return Self::default();
+            return Ok(Self::default());
         }
 
         let contents = fs::read_to_string(&path).expect("Failed to read current state file");
-        toml::from_str(&contents).expect("Failed to parse current state file")
+        toml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e.message()))
     }
-}
\ No newline at end of file
+
+    /// State folder for this machine and dotfolder, so a dotfolder shared between machines
+    /// (or several dotfolders on one machine) never share state. Deploying into another home
+    /// with `--home` keeps its own state next to it.
+    pub fn dir(config: &Config) -> PathBuf {
+        let escape = |path: &Path| {
+            path.to_string_lossy()
+                .trim_start_matches('/')
+                .replace('%', "%25")
+                .replace('/', "%2F")
+        };
+        let mut key = escape(&expand_user_path(&config.dotfolder_path));
+        let home = get_home_dir();
+        if home != get_user_home_dir() {
+            key = format!("{}@{}", key, escape(&home));
+        }
+        get_state_dir().join(get_hostname()).join(key)
+    }
+
+    /// Older versions kept the state inside the dotfolder itself. It is still read until a
+    /// command writes the state, so read-only commands leave the dotfolder alone.
+    fn legacy_path(config: &Config) -> PathBuf {
+        expand_user_path(&config.dotfolder_path).join(STATE_FILE)
+    }
+
+    /// Drops the state file of older versions once the state was written to `path`.
+    fn remove_legacy_state(config: &Config, path: &Path) {
+        let legacy_path = Self::legacy_path(config);
+        if !legacy_path.is_file() {
+            return;
+        }
+        delete(&legacy_path);
+        eprintln!(
+            "{} Moved {} to {}",
+            Yellow.paint("!"),
+            legacy_path.display(),
+            path.display()
+        );
+    }
+}
@@ -1,12 +1,117 @@
-use crate::config::{Config, DuplicateBehavior, OnDelinkBehavior};
-use crate::current_state::CurrentState;
-use crate::utils::{copy_all, delete, expand_path, get_home_and_dot_path, get_path_in_dotfolder};
+use crate::config::{Config, DuplicateBehavior, OnDelinkBehavior, PathEntry};
+use crate::current_state::{CurrentState, DeployMode, Outcome, PathRecord};
+use crate::journal::Journal;
+use crate::utils::{
+    backup_path, contract_path, expand_path, expand_user_path, find_links_into,
+    get_home_and_dot_path, get_home_dir, get_link_style, get_link_target, get_path_in_dotfolder,
+    is_ignored, is_link_to, is_real_dir, last_modified, list_files, load_ignore_patterns,
+    resolve_link, same_content,
+};
+use ansi_term::Colour;
 use ansi_term::Colour::*;
-use dialoguer::MultiSelect;
+use dialoguer::{MultiSelect, Select};
 use std::collections::HashSet;
 use std::fs;
-use std::os::unix::fs::symlink;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How many folders below the parent of each tracked path `lazydot state rebuild` looks into.
+pub const DEFAULT_SCAN_DEPTH: usize = 2;
+
+/// What `sync` would do with a tracked path.
+#[derive(Debug, PartialEq)]
+pub enum PlannedAction {
+    /// Already linked to the dotfolder copy.
+    Linked,
+    /// Only in the dotfolder, a link will be created.
+    Link,
+    /// Only in home, it will be moved into the dotfolder and linked.
+    Absorb,
+    /// Exists on both sides, resolved with `on_duplicate`.
+    Conflict,
+    /// Exists on neither side, skipped.
+    Missing,
+}
+
+/// What `gc` does with a link into the dotfolder that no tracked path accounts for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OrphanAction {
+    /// Replaces the link with a copy of what it points to.
+    Delink,
+    Remove,
+    Leave,
+}
+
+impl OrphanAction {
+    fn label(&self) -> &'static str {
+        match self {
+            OrphanAction::Delink => "Delink it, copying the content",
+            OrphanAction::Remove => "Remove the link",
+            OrphanAction::Leave => "Leave it",
+        }
+    }
+}
+
+/// A path found on both sides while syncing with `on_duplicate = "ask"`.
+struct Duplicate {
+    path: String,
+    path_in_home: PathBuf,
+    path_in_dotfolder: PathBuf,
+    link_target: PathBuf,
+    mode: DeployMode,
+    on_duplicate: DuplicateBehavior,
+    kind: DuplicateKind,
+}
+
+/// What a duplicate stands for.
+#[derive(Clone, Copy, PartialEq)]
+enum DuplicateKind {
+    /// A tracked path, recorded in the state.
+    Path,
+    /// A file of a `granularity = "files"` folder, recorded with its folder.
+    FolderFile,
+    /// A file that differs between the two copies of a folder being merged. The losing version
+    /// is backed up, and the folder is linked once all of them are settled.
+    MergeConflict,
+}
+
+impl Duplicate {
+    /// The behavior that settles this duplicate. Copies with the same content are linked
+    /// without asking or backing anything up, unless `skip` says to leave them alone.
+    fn behavior(&self) -> DuplicateBehavior {
+        let identical = same_content(&self.path_in_home, &self.path_in_dotfolder);
+        match self.on_duplicate {
+            DuplicateBehavior::Skip => DuplicateBehavior::Skip,
+            _ if identical => DuplicateBehavior::OverwriteHome,
+            DuplicateBehavior::IdenticalOnly => DuplicateBehavior::Ask,
+            DuplicateBehavior::Merge
+                if !is_real_dir(&self.path_in_home) || !is_real_dir(&self.path_in_dotfolder) =>
+            {
+                DuplicateBehavior::Ask
+            }
+            DuplicateBehavior::Newest => {
+                if last_modified(&self.path_in_home) > last_modified(&self.path_in_dotfolder) {
+                    DuplicateBehavior::BackupDotfile
+                } else {
+                    DuplicateBehavior::BackupHome
+                }
+            }
+            behavior => behavior,
+        }
+    }
+
+    fn record(&self, outcome: Outcome) -> Option<PathRecord> {
+        (self.kind == DuplicateKind::Path).then(|| {
+            PathRecord::new(
+                &self.path,
+                &self.path_in_home,
+                &self.path_in_dotfolder,
+                self.mode,
+                outcome,
+            )
+        })
+    }
+}
 
 pub struct DotManager {
     pub(crate) config: Config,
@@ -15,111 +120,503 @@ pub struct DotManager {
 
 impl DotManager {
     pub fn new() -> DotManager {
-        let config = Config::new();
-        let dotfolder_path = expand_path(&config.dotfolder_path);
-        if !dotfolder_path.exists() {
-            fs::create_dir_all(&dotfolder_path).expect(&format!(
-                "Failed to create the dotfolder folder: {}",
-                dotfolder_path.display()
-            ));
+        let mut manager = Self::without_state();
+        match CurrentState::new(&manager.config) {
+            Ok(state) => manager.current_state = state,
+            Err(e) => manager.recover_state(&e),
         }
-        if !dotfolder_path.is_dir() {
+        manager
+    }
+
+    /// A manager that ignores the state file, for commands that replace it anyway.
+    pub fn without_state() -> DotManager {
+        let config = Config::new();
+        let dotfolder_path = expand_user_path(&config.dotfolder_path);
+        if dotfolder_path.exists() && !dotfolder_path.is_dir() {
             panic!("{} is not a directory", dotfolder_path.display());
         }
 
         Self {
-            current_state: CurrentState::new(&config),
+            current_state: CurrentState::default(),
             config,
         }
     }
 
+    /// Asks what to do with a state file that can't be read instead of giving up.
+    fn recover_state(&mut self, error: &str) {
+        println!("{} {}", Red.paint("✘"), error);
+        // Abort when there is no terminal to ask in
+        let choice = Select::new()
+            .with_prompt("The state file is damaged")
+            .items(&[
+                "Rebuild it from the links in home",
+                "Start from an empty state",
+                "Abort",
+            ])
+            .default(0)
+            .interact()
+            .unwrap_or(2);
+        match choice {
+            0 => self.rebuild_state(DEFAULT_SCAN_DEPTH),
+            1 => self.current_state = CurrentState::default(),
+            _ => std::process::exit(1),
+        }
+    }
+
+    fn state_dir(&self) -> PathBuf {
+        CurrentState::dir(&self.config)
+    }
+
+    pub(crate) fn begin_journal(&self, command: &str) -> Result<Journal, String> {
+        Journal::begin(
+            command,
+            &self.current_state,
+            &self.state_dir(),
+            self.config.privileges(),
+        )
+    }
+
     pub fn sync(&self) {
-        let paths_tobe_unlinked =
-            Self::find_paths_to_removed(&self.current_state.paths, &self.config.paths);
-        self.delink(&paths_tobe_unlinked);
+        let dotfolder_path = expand_user_path(&self.config.dotfolder_path);
+        if !dotfolder_path.exists() {
+            fs::create_dir_all(&dotfolder_path).unwrap_or_else(|_| {
+                panic!(
+                    "Failed to create the dotfolder folder: {}",
+                    dotfolder_path.display()
+                )
+            });
+        }
+
+        let mut journal = match self.begin_journal("sync") {
+            Ok(journal) => journal,
+            Err(e) => {
+                println!("{} {}", Red.paint("✘"), e);
+                return;
+            }
+        };
+        let paths_tobe_unlinked = Self::find_paths_to_removed(
+            &self.current_state.linked_paths(),
+            &self.config.tracked_paths(),
+        );
+        self.delink_paths(&paths_tobe_unlinked, &mut journal);
 
-        let mut duplicated_paths: Vec<(PathBuf, PathBuf)> = Vec::new();
+        let mut duplicated_paths: Vec<Duplicate> = Vec::new();
+        let mut records: Vec<PathRecord> = Vec::new();
 
-        for path in &self.config.paths {
+        for entry in self.config.active_entries() {
+            let path = &entry.path;
             print!("{}", Blue.paint("Linking: "));
             let (path_in_home, path_in_dotfolder) = get_home_and_dot_path(path);
+            let link_style = self.config.link_style_for(entry);
+            let mode = self.deploy_mode(entry, &path_in_home, &path_in_dotfolder);
+            let record =
+                |outcome| PathRecord::new(path, &path_in_home, &path_in_dotfolder, mode, outcome);
+
+            if mode == DeployMode::FileLinks {
+                let duplicates_before = duplicated_paths.len();
+                let outcome = self.sync_files(
+                    entry,
+                    &path_in_home,
+                    &path_in_dotfolder,
+                    &mut journal,
+                    &mut duplicated_paths,
+                );
+                records.push(record(outcome));
+                if duplicated_paths.len() > duplicates_before {
+                    println!(
+                        "{} {} has files in both home and the dotfolder",
+                        Yellow.paint("!"),
+                        path
+                    );
+                } else if outcome == Outcome::Linked {
+                    println!("{} {}", Green.paint("✔"), path);
+                }
+                continue;
+            }
+            let recorded_mode = self.current_state.get(path).and_then(|record| record.mode);
+            if recorded_mode == Some(DeployMode::FileLinks) && is_real_dir(&path_in_home) {
+                // Linked file by file before, the folder is linked as a whole now
+                if let Err(e) = Self::unlink_files(&path_in_home, &path_in_dotfolder, &mut journal)
+                {
+                    println!(
+                        "{} Failed to unlink the files of {}: {}",
+                        Red.paint("✘"),
+                        path,
+                        e
+                    );
+                    records.push(record(Outcome::Failed));
+                    continue;
+                }
+            }
 
             if path_in_home.is_symlink() && !path_in_home.exists() {
-                delete(&path_in_home);
+                println!(
+                    "{} Replacing a dangling link to {}",
+                    Yellow.paint("!"),
+                    resolve_link(&path_in_home).unwrap_or_default().display()
+                );
+                journal.unlink(&path_in_home);
             }
 
-            match (path_in_home.exists(), path_in_dotfolder.exists()) {
-                (true, false) => {
-                    copy_all(&path_in_home, &path_in_dotfolder).unwrap();
-                    delete(&path_in_home);
-                    if let Err(e) = symlink(&path_in_dotfolder, &path_in_home) {
-                        println!("{} Failed to create symlink: {}", Red.paint("✘"), e);
-                        continue;
-                    }
-                }
-                (false, true) => {
-                    if let Err(e) = symlink(&path_in_dotfolder, &path_in_home) {
-                        println!("{} Failed to create symlink: {}", Red.paint("✘"), e);
-                        continue;
-                    }
+            if is_link_to(&path_in_home, &path_in_dotfolder) {
+                if mode != DeployMode::Copy && get_link_style(&path_in_home) == Some(link_style) {
+                    println!("{} {}", Green.paint("✔"), path);
+                    records.push(record(Outcome::Linked));
+                    continue;
                 }
-                (true, true) => match self.config.defaults.on_duplicate {
-                    DuplicateBehavior::Ask => {
-                        if path_in_home
-                            .canonicalize()
-                            .expect("Failed to canonicalize path")
-                            .eq(&path_in_dotfolder)
-                        {
-                            continue;
-                        }
-                        duplicated_paths.push((path_in_home, path_in_dotfolder));
-                    }
-                    DuplicateBehavior::OverwriteHome => {
-                        delete(&path_in_home);
-                        if let Err(e) = symlink(&path_in_dotfolder, &path_in_home) {
-                            println!("{} Failed to create symlink: {}", Red.paint("✘"), e);
-                            continue;
-                        }
-                    }
-                    DuplicateBehavior::OverwriteDotfile => {
-                        delete(&path_in_dotfolder);
-                        copy_all(&path_in_home, &path_in_dotfolder).unwrap();
-                        delete(&path_in_home);
-                        if let Err(e) = symlink(&path_in_dotfolder, &path_in_home) {
-                            println!("{} Failed to create symlink: {}", Red.paint("✘"), e);
-                            continue;
-                        }
-                    }
-                    DuplicateBehavior::BackupHome => {
-                        let backup = path_in_home.with_extension("bak");
-                        fs::rename(&path_in_home, &backup)
-                            .expect("Failed to create backup of Home path");
-                        if let Err(e) = symlink(&path_in_dotfolder, &path_in_home) {
-                            println!("{} Failed to create symlink: {}", Red.paint("✘"), e);
-                            continue;
-                        }
+                // Linked with the other style, or now copied, re-create it
+                journal.unlink(&path_in_home);
+            }
+            if mode == DeployMode::Copy && same_content(&path_in_home, &path_in_dotfolder) {
+                println!("{} {}", Green.paint("✔"), path);
+                records.push(record(Outcome::Linked));
+                continue;
+            }
+
+            let link_target = || get_link_target(&path_in_dotfolder, &path_in_home, link_style);
+            let deploy = |journal: &mut Journal| {
+                Self::deploy(
+                    journal,
+                    mode,
+                    &link_target(),
+                    &path_in_dotfolder,
+                    &path_in_home,
+                )
+            };
+
+            let outcome = match (path_in_home.exists(), path_in_dotfolder.exists()) {
+                (true, false) => match journal.move_path(&path_in_home, &path_in_dotfolder) {
+                    Ok(()) => deploy(&mut journal),
+                    Err(e) => {
+                        println!(
+                            "{} Failed to move {} into the dotfolder: {}",
+                            Red.paint("✘"),
+                            path,
+                            e
+                        );
+                        Outcome::Failed
                     }
-                    DuplicateBehavior::Skip => {}
                 },
+                (false, true) => deploy(&mut journal),
+                (true, true) => {
+                    duplicated_paths.push(Duplicate {
+                        path: path.clone(),
+                        link_target: link_target(),
+                        mode,
+                        on_duplicate: self.config.on_duplicate_for(entry),
+                        path_in_home: path_in_home.clone(),
+                        path_in_dotfolder: path_in_dotfolder.clone(),
+                        kind: DuplicateKind::Path,
+                    });
+                    // Reported once settled with the other duplicates
+                    println!(
+                        "{} {} is in both home and the dotfolder",
+                        Yellow.paint("!"),
+                        path
+                    );
+                    continue;
+                }
                 (false, false) => {
                     println!(
                         "{} Warning: path doesn't exist in home or dotfolder, skipping.\n {}",
                         Yellow.paint("!"),
                         path_in_home.display()
                     );
+                    Outcome::Missing
                 }
+            };
+            records.push(record(outcome));
+            if outcome != Outcome::Failed {
+                println!("{} {}", Green.paint("✔"), path);
             }
-            println!("{} {}", Green.paint("✔"), path);
         }
 
         if !duplicated_paths.is_empty() {
-            self.process_duplicated(duplicated_paths);
+            records.extend(self.process_duplicated(duplicated_paths, &mut journal));
         }
 
-        self.current_state.save(&self.config);
+        // Remembered so a path keeps its own on_delink once removed from the config
+        for record in &mut records {
+            record.on_delink = self
+                .config
+                .paths
+                .iter()
+                .find(|entry| entry.path == record.path)
+                .and_then(|entry| entry.on_delink);
+        }
+        CurrentState { paths: records }.write(&self.config);
+        journal.finish();
+    }
+
+    /// How `entry` is deployed on this machine. `granularity = "files"` only applies to folders,
+    /// anything else is linked as a whole.
+    fn deploy_mode(
+        &self,
+        entry: &PathEntry,
+        path_in_home: &Path,
+        path_in_dotfolder: &Path,
+    ) -> DeployMode {
+        match self.config.deploy_mode_for(entry) {
+            DeployMode::FileLinks
+                if !path_in_dotfolder.is_dir()
+                    && (path_in_dotfolder.exists() || !is_real_dir(path_in_home)) =>
+            {
+                self.config.link_style_for(entry).into()
+            }
+            mode => mode,
+        }
+    }
+
+    /// Whether a path of the `granularity = "files"` folder of `entry`, relative to the folder,
+    /// is left out by the entry's `ignore` or by `.lazydotignore`.
+    fn ignored_in(&self, entry: &PathEntry, path_in_dotfolder: &Path) -> impl Fn(&Path) -> bool {
+        let dotfolder = expand_user_path(&self.config.dotfolder_path);
+        let patterns = load_ignore_patterns(&dotfolder);
+        let own = entry.ignore.clone().unwrap_or_default();
+        let folder = path_in_dotfolder
+            .strip_prefix(&dotfolder)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        move |relative: &Path| {
+            is_ignored(&own, relative) || is_ignored(&patterns, &folder.join(relative))
+        }
     }
 
-    fn process_duplicated(&self, duplicated_paths: Vec<(PathBuf, PathBuf)>) {
+    /// Links each file of a `granularity = "files"` folder into a real folder in home. Files
+    /// only in home are moved into the dotfolder first, ignored ones stay where they are.
+    fn sync_files(
+        &self,
+        entry: &PathEntry,
+        path_in_home: &Path,
+        path_in_dotfolder: &Path,
+        journal: &mut Journal,
+        duplicated_paths: &mut Vec<Duplicate>,
+    ) -> Outcome {
+        if path_in_home.is_symlink() {
+            if path_in_home.exists() && !is_link_to(path_in_home, path_in_dotfolder) {
+                println!(
+                    "{} {} links somewhere else, skipping",
+                    Yellow.paint("!"),
+                    entry.path
+                );
+                return Outcome::Skipped;
+            }
+            // Linked as a whole before, or dangling
+            journal.unlink(path_in_home);
+        }
+
+        let link_style = self.config.link_style_for(entry);
+        let ignored = self.ignored_in(entry, path_in_dotfolder);
+        for relative in list_files(path_in_home, &ignored) {
+            let (home_file, dot_file) = (
+                path_in_home.join(&relative),
+                path_in_dotfolder.join(&relative),
+            );
+            if !home_file.is_symlink() && !dot_file.exists() && !dot_file.is_symlink() {
+                journal
+                    .move_path(&home_file, &dot_file)
+                    .expect("Failed to move into the dotfolder");
+            }
+        }
+
+        let mut outcome = Outcome::Linked;
+        for relative in list_files(path_in_dotfolder, &ignored) {
+            let (home_file, dot_file) = (
+                path_in_home.join(&relative),
+                path_in_dotfolder.join(&relative),
+            );
+            let linked = is_link_to(&home_file, &dot_file);
+            if linked && get_link_style(&home_file) == Some(link_style) {
+                continue;
+            }
+            if linked || (home_file.is_symlink() && !home_file.exists()) {
+                journal.unlink(&home_file);
+            }
+            let link_target = get_link_target(&dot_file, &home_file, link_style);
+            if home_file.exists() || home_file.is_symlink() {
+                duplicated_paths.push(Duplicate {
+                    path: entry.path.clone(),
+                    path_in_home: home_file,
+                    path_in_dotfolder: dot_file,
+                    link_target,
+                    mode: link_style.into(),
+                    on_duplicate: self.config.on_duplicate_for(entry),
+                    kind: DuplicateKind::FolderFile,
+                });
+            } else if Self::link(journal, &link_target, &home_file) == Outcome::Failed {
+                outcome = Outcome::Failed;
+            }
+        }
+        outcome
+    }
+
+    /// Removes the links a `granularity = "files"` folder holds into the dotfolder, and the
+    /// folder itself when nothing else is left in it.
+    fn unlink_files(
+        path_in_home: &Path,
+        path_in_dotfolder: &Path,
+        journal: &mut Journal,
+    ) -> io::Result<()> {
+        for relative in list_files(path_in_home, &|_| false) {
+            let home_file = path_in_home.join(&relative);
+            if is_link_to(&home_file, &path_in_dotfolder.join(&relative)) {
+                journal.unlink(&home_file);
+            }
+        }
+        if list_files(path_in_home, &|_| false).is_empty() {
+            journal.delete(path_in_home)?;
+        }
+        Ok(())
+    }
+
+    /// Health of each file of a `granularity = "files"` folder, as `check` labels it: the files
+    /// of the dotfolder copy, then the ones only in home that sync would move there.
+    fn file_health(
+        &self,
+        entry: &PathEntry,
+        path_in_home: &Path,
+        path_in_dotfolder: &Path,
+    ) -> Vec<(PathBuf, &'static str, Colour)> {
+        let link_style = self.config.link_style_for(entry);
+        let ignored = self.ignored_in(entry, path_in_dotfolder);
+        let mut files = Vec::new();
+        for relative in list_files(path_in_dotfolder, &ignored) {
+            let (home_file, dot_file) = (
+                path_in_home.join(&relative),
+                path_in_dotfolder.join(&relative),
+            );
+            let (label, color) = if is_link_to(&home_file, &dot_file) {
+                if get_link_style(&home_file) == Some(link_style) {
+                    ("[LINKED]", Green)
+                } else {
+                    ("[LINK-STYLE]", Yellow)
+                }
+            } else if home_file.is_symlink() && home_file.exists() {
+                ("[WRONG-TGT]", Red)
+            } else if home_file.is_symlink() {
+                ("[BROKEN-LNK]", Red)
+            } else if home_file.exists() {
+                ("[CONFLICT]", Yellow)
+            } else {
+                ("[UNLINKED]", Yellow)
+            };
+            files.push((home_file, label, color));
+        }
+        for relative in list_files(path_in_home, &ignored) {
+            let (home_file, dot_file) = (
+                path_in_home.join(&relative),
+                path_in_dotfolder.join(&relative),
+            );
+            if !home_file.is_symlink() && !dot_file.exists() && !dot_file.is_symlink() {
+                files.push((home_file, "[UNLINKED]", Yellow));
+            }
+        }
+        files
+    }
+
+    /// Puts the dotfolder copy in home as `mode` says, reporting a failure instead of aborting
+    /// the sync.
+    fn deploy(
+        journal: &mut Journal,
+        mode: DeployMode,
+        link_target: &Path,
+        path_in_dotfolder: &Path,
+        path_in_home: &Path,
+    ) -> Outcome {
+        if mode != DeployMode::Copy {
+            return Self::link(journal, link_target, path_in_home);
+        }
+        match journal.copy(path_in_dotfolder, path_in_home) {
+            Ok(()) => Outcome::Linked,
+            Err(e) => {
+                println!("{} Failed to copy: {}", Red.paint("✘"), e);
+                Outcome::Failed
+            }
+        }
+    }
+
+    /// Creates the symlink at `path_in_home`, reporting a failure instead of aborting the sync.
+    fn link(journal: &mut Journal, link_target: &Path, path_in_home: &Path) -> Outcome {
+        match journal.link(link_target, path_in_home) {
+            Ok(()) => Outcome::Linked,
+            Err(e) => {
+                println!("{} Failed to create symlink: {}", Red.paint("✘"), e);
+                Outcome::Failed
+            }
+        }
+    }
+
+    /// Settles the paths found in both home and the dotfolder with their `on_duplicate`,
+    /// asking about the ones set to `ask` all at once.
+    fn process_duplicated(
+        &self,
+        duplicated_paths: Vec<Duplicate>,
+        journal: &mut Journal,
+    ) -> Vec<PathRecord> {
+        let (to_ask, to_settle): (Vec<_>, Vec<_>) = duplicated_paths
+            .into_iter()
+            .map(|duplicate| (duplicate.behavior(), duplicate))
+            .partition(|(behavior, _)| *behavior == DuplicateBehavior::Ask);
+        let mut to_ask: Vec<Duplicate> =
+            to_ask.into_iter().map(|(_, duplicate)| duplicate).collect();
+        let (to_merge, to_settle): (Vec<_>, Vec<_>) = to_settle
+            .into_iter()
+            .partition(|(behavior, _)| *behavior == DuplicateBehavior::Merge);
+
+        let mut records = Vec::new();
+        for (behavior, duplicate) in &to_settle {
+            records.extend(self.settle_and_report(duplicate, *behavior, journal));
+        }
+        // Files differing between both copies of a folder are asked about with the rest
+        for (_, duplicate) in &to_merge {
+            for (path_in_home, path_in_dotfolder) in
+                Self::merge_conflicts(&duplicate.path_in_home, &duplicate.path_in_dotfolder)
+            {
+                to_ask.push(Duplicate {
+                    path: duplicate.path.clone(),
+                    link_target: path_in_dotfolder.clone(),
+                    path_in_home,
+                    path_in_dotfolder,
+                    mode: duplicate.mode,
+                    on_duplicate: DuplicateBehavior::Ask,
+                    kind: DuplicateKind::MergeConflict,
+                });
+            }
+        }
+        if !to_ask.is_empty() {
+            records.extend(self.ask_duplicated(&to_ask, journal));
+        }
+        for (behavior, duplicate) in &to_merge {
+            records.extend(self.settle_and_report(duplicate, *behavior, journal));
+        }
+        records
+    }
+
+    /// Settles a duplicate that wasn't asked about and prints how it ended. Failures and skips
+    /// print their own reason.
+    fn settle_and_report(
+        &self,
+        duplicate: &Duplicate,
+        behavior: DuplicateBehavior,
+        journal: &mut Journal,
+    ) -> Option<PathRecord> {
+        print!("{}", Blue.paint("Settling: "));
+        let outcome = self.settle_duplicate(duplicate, behavior, journal);
+        if outcome == Outcome::Linked {
+            println!(
+                "{} {}",
+                Green.paint("✔"),
+                contract_path(&duplicate.path_in_home)
+            );
+        }
+        duplicate.record(outcome)
+    }
+
+    fn ask_duplicated(
+        &self,
+        duplicated_paths: &[Duplicate],
+        journal: &mut Journal,
+    ) -> Vec<PathRecord> {
         println!(
             "\n{}\n- 'Select All' = keep all home versions\n- No selection = use dotfolder versions\n",
             Yellow.paint(
@@ -131,15 +628,21 @@ impl DotManager {
             vec!["Select All"],
             duplicated_paths
                 .iter()
-                .map(|it| it.0.to_str().unwrap())
+                .map(|it| it.path_in_home.to_str().unwrap())
                 .collect::<Vec<_>>(),
         ]
         .concat();
 
-        let selected = MultiSelect::new()
-            .items(&options)
-            .interact()
-            .expect("Failed to select");
+        let Ok(selected) = MultiSelect::new().items(&options).interact() else {
+            println!(
+                "{} No terminal to ask in, leaving both versions as they are",
+                Yellow.paint("!")
+            );
+            return duplicated_paths
+                .iter()
+                .filter_map(|duplicate| duplicate.record(Outcome::Skipped))
+                .collect();
+        };
 
         let selected_indices = if !selected.is_empty() && selected[0] == 0 {
             (0..duplicated_paths.len()).collect::<Vec<_>>()
@@ -147,42 +650,298 @@ impl DotManager {
             selected.iter().map(|i| i - 1).collect::<Vec<_>>()
         };
 
-        for index in &selected_indices {
-            print!("{}", Blue.paint("Overwriting Home with Dotfile: "));
-            let path = duplicated_paths.get(*index).expect("Index out of range");
-            delete(&path.1);
-            copy_all(&path.0, &path.1).unwrap();
-            delete(&path.0);
-            if let Err(e) = symlink(&path.1, &path.0) {
-                println!("{} Failed to create symlink: {}", Red.paint("✘"), e);
-                continue;
+        let mut records = Vec::new();
+        for (i, duplicate) in duplicated_paths.iter().enumerate() {
+            let behavior = if selected_indices.contains(&i) {
+                print!("{}", Blue.paint("Overwriting Home with Dotfile: "));
+                DuplicateBehavior::OverwriteDotfile
+            } else {
+                print!("{}", Blue.paint("Keeping Home: "));
+                DuplicateBehavior::OverwriteHome
+            };
+            let outcome = self.settle_duplicate(duplicate, behavior, journal);
+            if outcome == Outcome::Linked {
+                println!("{} {}", Green.paint("✔"), duplicate.path_in_home.display());
             }
-            println!("{} {}", Green.paint("✔"), path.0.display());
+            records.extend(duplicate.record(outcome));
+        }
+        records
+    }
+
+    /// Applies `behavior` to a path found in both home and the dotfolder, then deploys it.
+    fn settle_duplicate(
+        &self,
+        duplicate: &Duplicate,
+        behavior: DuplicateBehavior,
+        journal: &mut Journal,
+    ) -> Outcome {
+        let (path_in_home, path_in_dotfolder) =
+            (&duplicate.path_in_home, &duplicate.path_in_dotfolder);
+        if duplicate.kind == DuplicateKind::MergeConflict {
+            return self.settle_merge_conflict(duplicate, behavior, journal);
+        }
+        let settled = match behavior {
+            // Settled into another behavior by `Duplicate::behavior`
+            DuplicateBehavior::Ask
+            | DuplicateBehavior::Newest
+            | DuplicateBehavior::IdenticalOnly => {
+                return Outcome::Skipped;
+            }
+            DuplicateBehavior::Skip => {
+                println!(
+                    "{} {} skipped, it is left as it is in home and the dotfolder",
+                    Yellow.paint("!"),
+                    contract_path(path_in_home)
+                );
+                return Outcome::Skipped;
+            }
+            DuplicateBehavior::OverwriteHome => journal.delete(path_in_home),
+            DuplicateBehavior::OverwriteDotfile => journal
+                .delete(path_in_dotfolder)
+                .and_then(|()| journal.move_path(path_in_home, path_in_dotfolder)),
+            // Next to a file of a `granularity = "files"` folder, the next sync would take the
+            // backup for a new file of the folder
+            DuplicateBehavior::BackupHome if duplicate.kind == DuplicateKind::FolderFile => {
+                journal.move_path(path_in_home, &self.backup_in_state(path_in_home))
+            }
+            DuplicateBehavior::BackupHome => {
+                journal.rename(path_in_home, &backup_path(path_in_home))
+            }
+            // Kept out of the dotfolder, so the backup is never committed
+            DuplicateBehavior::BackupDotfile => journal
+                .move_path(path_in_dotfolder, &self.backup_in_state(path_in_dotfolder))
+                .and_then(|()| journal.move_path(path_in_home, path_in_dotfolder)),
+            // The differing files were asked about, the ones left were skipped
+            DuplicateBehavior::Merge
+                if !Self::merge_conflicts(path_in_home, path_in_dotfolder).is_empty() =>
+            {
+                println!(
+                    "{} {} still has files that differ from the dotfolder, not linked",
+                    Yellow.paint("!"),
+                    duplicate.path
+                );
+                return Outcome::Skipped;
+            }
+            DuplicateBehavior::Merge => {
+                Self::merge_into_dotfolder(path_in_home, path_in_dotfolder, journal);
+                journal.delete(path_in_home)
+            }
+        };
+        if let Err(e) = settled {
+            println!(
+                "{} Failed to settle {}: {}",
+                Red.paint("✘"),
+                contract_path(path_in_home),
+                e
+            );
+            return Outcome::Failed;
         }
+        Self::deploy(
+            journal,
+            duplicate.mode,
+            &duplicate.link_target,
+            path_in_dotfolder,
+            path_in_home,
+        )
+    }
+
+    /// Where a backup of `path` is kept out of home and the dotfolder: in the `backups` folder
+    /// of the state, under `dotfolder/` or `home/` at the same relative path.
+    fn backup_in_state(&self, path: &Path) -> PathBuf {
+        let dotfolder = expand_user_path(&self.config.dotfolder_path);
+        let home = get_home_dir();
+        let (side, relative) = match (path.strip_prefix(&dotfolder), path.strip_prefix(&home)) {
+            (Ok(relative), _) => ("dotfolder", relative),
+            (_, Ok(relative)) => ("home", relative),
+            _ => ("root", path.strip_prefix("/").unwrap_or(path)),
+        };
+        backup_path(&self.state_dir().join("backups").join(side).join(relative))
+    }
+
+    /// Files that differ between the home and dotfolder copies of a folder, home version first.
+    fn merge_conflicts(home: &Path, dotfolder: &Path) -> Vec<(PathBuf, PathBuf)> {
+        let mut entries: Vec<PathBuf> = fs::read_dir(home)
+            .expect("Failed to read the home folder to merge")
+            .flatten()
+            .map(|entry| entry.path())
+            .collect();
+        entries.sort();
 
-        for (i, path) in duplicated_paths.into_iter().enumerate() {
-            if selected_indices.contains(&i) {
+        let mut conflicts = Vec::new();
+        for home_entry in entries {
+            let name = home_entry.file_name().expect("Failed to get the file name");
+            let dot_entry = dotfolder.join(name);
+            if !dot_entry.exists() && !dot_entry.is_symlink() {
                 continue;
             }
-            print!("{}", Blue.paint("Keeping Home: "));
-            delete(&path.0);
-            if let Err(e) = symlink(&path.1, &path.0) {
-                println!("{} Failed to create symlink: {}", Red.paint("✘"), e);
+            if is_real_dir(&home_entry) && is_real_dir(&dot_entry) {
+                conflicts.extend(Self::merge_conflicts(&home_entry, &dot_entry));
+            } else if !same_content(&home_entry, &dot_entry) {
+                conflicts.push((home_entry, dot_entry));
+            }
+        }
+        conflicts
+    }
+
+    /// Moves what only the home copy of a folder has into the dotfolder copy, once no file
+    /// differs between both. Files with the same content are kept once.
+    fn merge_into_dotfolder(home: &Path, dotfolder: &Path, journal: &mut Journal) {
+        let entries = fs::read_dir(home).expect("Failed to read the home folder to merge");
+        for home_entry in entries.flatten().map(|entry| entry.path()) {
+            let name = home_entry.file_name().expect("Failed to get the file name");
+            let dot_entry = dotfolder.join(name);
+            if !dot_entry.exists() && !dot_entry.is_symlink() {
+                journal
+                    .move_path(&home_entry, &dot_entry)
+                    .expect("Failed to move into the dotfolder");
+            } else if is_real_dir(&home_entry) && is_real_dir(&dot_entry) {
+                Self::merge_into_dotfolder(&home_entry, &dot_entry, journal);
+            }
+        }
+    }
+
+    /// Keeps the version of a differing file chosen in `ask_duplicated` and backs up the other
+    /// out of home and the dotfolder. Nothing is linked here, the whole folder is once merged.
+    fn settle_merge_conflict(
+        &self,
+        duplicate: &Duplicate,
+        behavior: DuplicateBehavior,
+        journal: &mut Journal,
+    ) -> Outcome {
+        let (path_in_home, path_in_dotfolder) =
+            (&duplicate.path_in_home, &duplicate.path_in_dotfolder);
+        let settled = match behavior {
+            DuplicateBehavior::OverwriteDotfile => journal
+                .move_path(path_in_dotfolder, &self.backup_in_state(path_in_dotfolder))
+                .and_then(|()| journal.move_path(path_in_home, path_in_dotfolder)),
+            _ => journal.move_path(path_in_home, &self.backup_in_state(path_in_home)),
+        };
+        match settled {
+            Ok(()) => Outcome::Linked,
+            Err(e) => {
+                println!(
+                    "{} Failed to settle {}: {}",
+                    Red.paint("✘"),
+                    contract_path(path_in_home),
+                    e
+                );
+                Outcome::Failed
+            }
+        }
+    }
+
+    /// The `on_delink` of `path`: its own in the config, or the one recorded when it was
+    /// linked for a path removed from the config since. A path excluded by `when` always keeps
+    /// its dotfolder copy, the other machines still need it.
+    fn on_delink_for(&self, path_in_home: &Path) -> OnDelinkBehavior {
+        match self.config.entry_for(path_in_home) {
+            Some(entry) if entry.excluded_because().is_some() => OnDelinkBehavior::Keep,
+            Some(entry) => self.config.on_delink_for(entry),
+            None => self
+                .current_state
+                .find(path_in_home)
+                .and_then(|record| record.on_delink)
+                .unwrap_or(self.config.defaults.on_delink),
+        }
+    }
+
+    /// Replaces each link of a `granularity = "files"` folder with the file it points to. Files
+    /// the folder holds of its own are left alone.
+    fn delink_files(&self, path: &str, path_in_home: &Path, journal: &mut Journal) {
+        let path_in_dotfolder =
+            get_path_in_dotfolder(path_in_home).expect("Failed to get path in dotfolder");
+        let on_delink = self.on_delink_for(path_in_home);
+        for relative in list_files(path_in_home, &|_| false) {
+            let (home_file, dot_file) = (
+                path_in_home.join(&relative),
+                path_in_dotfolder.join(&relative),
+            );
+            if !is_link_to(&home_file, &dot_file) {
                 continue;
             }
-            println!("{} {}", Green.paint("✔"), path.0.display());
+            journal.unlink(&home_file);
+            match on_delink {
+                OnDelinkBehavior::Remove => journal.move_path(&dot_file, &home_file),
+                OnDelinkBehavior::Keep => journal.copy(&dot_file, &home_file),
+            }
+            .expect("Failed to copy from dotfolder to home");
+        }
+        if matches!(on_delink, OnDelinkBehavior::Remove)
+            && path_in_dotfolder.exists()
+            && list_files(&path_in_dotfolder, &|_| false).is_empty()
+            && let Err(e) = journal.delete(&path_in_dotfolder)
+        {
+            println!("{} Failed to remove {}: {}", Red.paint("✘"), path, e);
+            return;
+        }
+        println!("{} {}", Green.paint("✔"), path);
+    }
+
+    /// A copied path already is a real file in home, only the dotfolder copy may go.
+    fn delink_copy(&self, path: &str, path_in_home: &Path, journal: &mut Journal) {
+        let path_in_dotfolder =
+            get_path_in_dotfolder(path_in_home).expect("Failed to get path in dotfolder");
+        match self.on_delink_for(path_in_home) {
+            OnDelinkBehavior::Remove if same_content(path_in_home, &path_in_dotfolder) => {
+                if let Err(e) = journal.delete(&path_in_dotfolder) {
+                    println!("{} Failed to remove {}: {}", Red.paint("✘"), path, e);
+                    return;
+                }
+            }
+            OnDelinkBehavior::Remove if path_in_dotfolder.exists() => {
+                println!(
+                    "{} {} differs from the dotfolder, keeping both",
+                    Yellow.paint("!"),
+                    path
+                );
+                return;
+            }
+            _ => {}
         }
+        println!("{} {}", Green.paint("✔"), path);
     }
 
     pub fn delink_all(&self) {
-        self.delink(&self.config.paths);
+        self.delink(&self.config.tracked_paths());
     }
 
     pub fn delink(&self, paths: &[String]) {
+        let mut journal = match self.begin_journal("disable-link") {
+            Ok(journal) => journal,
+            Err(e) => {
+                println!("{} {}", Red.paint("✘"), e);
+                return;
+            }
+        };
+        self.delink_paths(paths, &mut journal);
+        journal.finish();
+    }
+
+    fn delink_paths(&self, paths: &[String], journal: &mut Journal) {
         for path in paths {
             print!("{}", Yellow.paint("Unlinking: "));
-            let path_in_home = expand_path(path);
+            // The shell expands `~`, and paths in the dotfolder stand for their place in home
+            let path_in_home = match self.config.restrict_to_home(path.clone()) {
+                Ok(restricted) => expand_path(&restricted),
+                Err(_) => expand_path(path),
+            };
 
+            let recorded_mode = self
+                .current_state
+                .find(&path_in_home)
+                .and_then(|record| record.mode);
+            if recorded_mode == Some(DeployMode::Copy) && !path_in_home.is_symlink() {
+                self.delink_copy(path, &path_in_home, journal);
+                continue;
+            }
+            let unfolded = recorded_mode == Some(DeployMode::FileLinks)
+                || self.config.entry_for(&path_in_home).is_some_and(|entry| {
+                    self.config.deploy_mode_for(entry) == DeployMode::FileLinks
+                });
+            if unfolded && is_real_dir(&path_in_home) {
+                self.delink_files(path, &path_in_home, journal);
+                continue;
+            }
             if !path_in_home.is_symlink() {
                 println!("{} is not a symlink", Red.paint(path));
                 continue;
@@ -196,27 +955,220 @@ impl DotManager {
                 continue;
             }
 
-            if !path_in_home
-                .canonicalize()
-                .expect("Failed to canonicalize path")
-                .eq(&path_in_dotfolder)
-            {
+            if !is_link_to(&path_in_home, &path_in_dotfolder) {
                 println!("{} is not a symlink to dotfolder", Red.paint(path));
                 continue;
             }
 
-            delete(&path_in_home);
-            copy_all(&path_in_dotfolder, &path_in_home)
-                .expect("Failed to copy from dotfolder to home");
+            journal.unlink(&path_in_home);
+            match self.on_delink_for(&path_in_home) {
+                OnDelinkBehavior::Remove => journal.move_path(&path_in_dotfolder, &path_in_home),
+                OnDelinkBehavior::Keep => journal.copy(&path_in_dotfolder, &path_in_home),
+            }
+            .expect("Failed to copy from dotfolder to home");
+            println!("{} {}", Green.paint("✔"), path);
+        }
+    }
+
+    /// Symlinks in home pointing into the dotfolder, looked up in home and next to the tracked
+    /// paths, at most `depth` folders below them.
+    pub fn find_links(&self, depth: usize) -> Vec<PathBuf> {
+        let mut roots: Vec<PathBuf> = self
+            .config
+            .paths
+            .iter()
+            .filter_map(|entry| expand_path(&entry.path).parent().map(Path::to_path_buf))
+            .collect();
+        roots.push(get_home_dir());
+        roots.sort();
+        roots.dedup();
+        find_links_into(
+            &expand_user_path(&self.config.dotfolder_path),
+            &roots,
+            depth,
+        )
+    }
+
+    /// Links into the dotfolder found by `find_links` that no tracked path accounts for.
+    pub fn find_orphan_links(&self, depth: usize) -> Vec<PathBuf> {
+        let tracked: Vec<PathBuf> = self
+            .config
+            .active_entries()
+            .map(|entry| expand_path(&entry.path))
+            .collect();
+        let unfolded = self.unfolded_folders();
+        self.find_links(depth)
+            .into_iter()
+            .filter(|link| !tracked.contains(link))
+            .filter(|link| !unfolded.iter().any(|(_, folder)| link.starts_with(folder)))
+            .collect()
+    }
+
+    /// Tracked `granularity = "files"` folders and where they are in home.
+    fn unfolded_folders(&self) -> Vec<(String, PathBuf)> {
+        self.config
+            .active_entries()
+            .filter(|entry| self.config.deploy_mode_for(entry) == DeployMode::FileLinks)
+            .map(|entry| (entry.path.clone(), expand_path(&entry.path)))
+            .collect()
+    }
+
+    /// Lists untracked links into the dotfolder and offers to delink or remove each one.
+    pub fn gc(&self, depth: usize) {
+        self.gc_with(depth, |link, actions| {
+            let items: Vec<&str> = actions.iter().map(OrphanAction::label).collect();
+            // Leave everything as is when there is no terminal to ask in
+            let choice = Select::new()
+                .with_prompt(contract_path(link))
+                .items(&items)
+                .default(actions.len() - 1)
+                .interact()
+                .unwrap_or(actions.len() - 1);
+            actions[choice]
+        });
+    }
+
+    /// `gc` with the action for each link picked by `choose` among the ones offered for it.
+    pub(crate) fn gc_with(
+        &self,
+        depth: usize,
+        choose: impl Fn(&Path, &[OrphanAction]) -> OrphanAction,
+    ) {
+        let orphans = self.find_orphan_links(depth);
+        if orphans.is_empty() {
+            println!("{} No untracked links into the dotfolder", Green.paint("✔"));
+            return;
+        }
+        let display = |link: &Path| contract_path(link);
+        for link in &orphans {
+            let target = resolve_link(link).unwrap_or_default();
+            let dangling = if target.exists() { "" } else { " (dangling)" };
+            println!(
+                "{} {} -> {}{}",
+                Yellow.paint("??"),
+                display(link),
+                target.display(),
+                Red.paint(dangling)
+            );
+        }
+        println!();
+
+        let mut journal = match self.begin_journal("gc") {
+            Ok(journal) => journal,
+            Err(e) => {
+                println!("{} {}", Red.paint("✘"), e);
+                return;
+            }
+        };
+        for link in &orphans {
+            let target = resolve_link(link).unwrap_or_default();
+            let actions: &[OrphanAction] = if target.exists() {
+                &[
+                    OrphanAction::Delink,
+                    OrphanAction::Remove,
+                    OrphanAction::Leave,
+                ]
+            } else {
+                &[OrphanAction::Remove, OrphanAction::Leave]
+            };
+            match choose(link, actions) {
+                // The target may still be the dotfolder copy of a tracked path, never move it
+                OrphanAction::Delink => {
+                    journal.unlink(link);
+                    journal
+                        .copy(&target, link)
+                        .expect("Failed to copy from dotfolder to home");
+                    println!("{} Delinked {}", Green.paint("✔"), display(link));
+                }
+                OrphanAction::Remove => {
+                    journal.unlink(link);
+                    println!("{} Removed {}", Green.paint("✔"), display(link));
+                }
+                OrphanAction::Leave => {}
+            }
+        }
+        journal.finish();
+    }
 
-            match self.config.defaults.on_delink {
-                OnDelinkBehavior::Remove => {
-                    delete(&path_in_dotfolder);
+    /// Regenerates the state file from the links found in home, so paths removed from the
+    /// config while the state was lost are still unlinked by the next sync.
+    pub fn rebuild_state(&mut self, depth: usize) {
+        let tracked = self.config.tracked_paths();
+        let unfolded = self.unfolded_folders();
+        let mut records: Vec<PathRecord> = Vec::new();
+        for link in self.find_links(depth) {
+            // Dangling links are cleaned up by sync
+            let Some(target) = resolve_link(&link).filter(|target| target.exists()) else {
+                continue;
+            };
+            // A link per file stands for its whole folder
+            if let Some((path, folder)) =
+                unfolded.iter().find(|(_, folder)| link.starts_with(folder))
+            {
+                if records.iter().all(|record| record.path != *path) {
+                    let (_, path_in_dotfolder) = get_home_and_dot_path(path);
+                    records.push(PathRecord::new(
+                        path,
+                        folder,
+                        &path_in_dotfolder,
+                        DeployMode::FileLinks,
+                        Outcome::Linked,
+                    ));
+                    println!("{} {}", Green.paint("✔"), path);
                 }
-                OnDelinkBehavior::Keep => {}
+                continue;
             }
-            println!("{} {}", Green.paint("✔"), path);
+            let path = tracked
+                .iter()
+                .find(|path| expand_path(path) == link)
+                .cloned()
+                .unwrap_or_else(|| contract_path(&link));
+            let style = get_link_style(&link).unwrap_or_default();
+            records.push(PathRecord::new(
+                &path,
+                &link,
+                &target,
+                style.into(),
+                Outcome::Linked,
+            ));
+
+            if tracked.contains(&path) {
+                println!("{} {}", Green.paint("✔"), path);
+            } else {
+                println!(
+                    "{} {}{}",
+                    Yellow.paint("--"),
+                    path,
+                    Fixed(8).paint(" (not tracked, the next sync will unlink it)")
+                );
+            }
+        }
+        if records.is_empty() {
+            println!("{} No links to the dotfolder found", Yellow.paint("!"));
+        }
+
+        self.current_state = CurrentState { paths: records };
+        self.current_state.write(&self.config);
+    }
+
+    /// Reverts the last `sync`, `disable-link`, `untracked`, `gc` or `import stow` run and restores
+    /// the previous state.
+    pub fn undo(&self) {
+        let Some(journal) = Journal::load(&self.state_dir()) else {
+            println!("{} Nothing to undo", Yellow.paint("!"));
+            return;
+        };
+        println!(
+            "{}",
+            Blue.paint(format!("Undoing the last `{}`:", journal.command))
+        );
+        // The backups may be the only copy of what was overwritten, keep them if anything failed
+        if let Err(e) = journal.revert() {
+            println!("{} {}", Red.paint("✘"), e);
+            return;
         }
+        journal.previous_state.write(&self.config);
+        journal.discard();
     }
 
     fn find_paths_to_removed(current_paths: &[String], config_paths: &[String]) -> Vec<String> {
@@ -239,34 +1191,335 @@ impl DotManager {
             .collect()
     }
 
-    pub fn status(&self) {
-        let paths_tobe_removed =
-            Self::find_paths_to_removed(&self.current_state.paths, &self.config.paths);
-        let paths_to_be_added =
-            Self::find_paths_to_be_added(&self.current_state.paths, &self.config.paths);
+    /// Classifies every tracked path by what `sync` would do with it.
+    pub fn plan(&self) -> Vec<(String, PlannedAction)> {
+        self.config
+            .active_entries()
+            .map(|entry| {
+                let (home, dot) = get_home_and_dot_path(&entry.path);
+                let mode = self.deploy_mode(entry, &home, &dot);
+                let copied = mode == DeployMode::Copy && same_content(&home, &dot);
+                let unfolded = mode == DeployMode::FileLinks
+                    && is_real_dir(&home)
+                    && self
+                        .file_health(entry, &home, &dot)
+                        .iter()
+                        .all(|(_, label, _)| *label == "[LINKED]");
+                let action = if (mode != DeployMode::FileLinks && is_link_to(&home, &dot))
+                    || copied
+                    || unfolded
+                {
+                    PlannedAction::Linked
+                } else {
+                    // Dangling symlinks are replaced by sync
+                    let home_exists = home.exists();
+                    match (home_exists, dot.exists()) {
+                        (true, true) => PlannedAction::Conflict,
+                        (true, false) => PlannedAction::Absorb,
+                        (false, true) => PlannedAction::Link,
+                        (false, false) => PlannedAction::Missing,
+                    }
+                };
+                (entry.path.clone(), action)
+            })
+            .collect()
+    }
+
+    pub fn print_plan(&self) {
+        for (path, action) in self.plan() {
+            let (label, color) = match action {
+                PlannedAction::Linked => ("[LINKED]", Green),
+                PlannedAction::Link => ("[LINK]", Blue),
+                PlannedAction::Absorb => ("[ABSORB]", Blue),
+                PlannedAction::Conflict => ("[CONFLICT]", Yellow),
+                PlannedAction::Missing => ("[BOTH-MISS]", Fixed(8)),
+            };
+            if action == PlannedAction::Conflict {
+                println!(
+                    "{:<13} {} (on_duplicate = {})",
+                    color.paint(label),
+                    path,
+                    self.config
+                        .paths
+                        .iter()
+                        .find(|entry| entry.path == path)
+                        .map_or(self.config.defaults.on_duplicate, |entry| {
+                            self.config.on_duplicate_for(entry)
+                        })
+                        .as_str()
+                );
+            } else {
+                println!("{:<13} {}", color.paint(label), path);
+            }
+        }
+    }
 
-        paths_to_be_added
+    /// Entries of the dotfolder not covered by any tracked path. Folders holding tracked paths
+    /// are walked into, so only the top-most untracked entries are returned.
+    pub fn find_untracked(&self) -> Vec<PathBuf> {
+        let dotfolder = expand_user_path(&self.config.dotfolder_path);
+        if !dotfolder.is_dir() {
+            return Vec::new();
+        }
+        let tracked: Vec<PathBuf> = self
+            .config
+            .paths
             .iter()
-            .for_each(|p| println!("{} {}", Green.paint("++"), p));
-        paths_tobe_removed
+            .map(|entry| get_home_and_dot_path(&entry.path).1)
+            .collect();
+        let patterns = load_ignore_patterns(&dotfolder);
+
+        let mut untracked = Vec::new();
+        Self::collect_untracked(&dotfolder, &dotfolder, &tracked, &patterns, &mut untracked);
+        untracked.sort();
+        untracked
+    }
+
+    fn collect_untracked(
+        dir: &Path,
+        dotfolder: &Path,
+        tracked: &[PathBuf],
+        patterns: &[String],
+        untracked: &mut Vec<PathBuf>,
+    ) {
+        let entries = fs::read_dir(dir).expect("Failed to read the dotfolder");
+        for entry in entries {
+            let path = entry.expect("Failed to read the dotfolder").path();
+            let relative = path
+                .strip_prefix(dotfolder)
+                .expect("Failed to strip prefix");
+            if is_ignored(patterns, relative) || tracked.iter().any(|t| path.starts_with(t)) {
+                continue;
+            }
+            if path.is_dir() && !path.is_symlink() && tracked.iter().any(|t| t.starts_with(&path)) {
+                Self::collect_untracked(&path, dotfolder, tracked, patterns, untracked);
+                continue;
+            }
+            untracked.push(path);
+        }
+    }
+
+    /// Lists untracked dotfolder entries and offers to track or delete each one.
+    pub fn untracked(&mut self) {
+        let untracked = self.find_untracked();
+        if untracked.is_empty() {
+            println!(
+                "{} Every entry in the dotfolder is tracked",
+                Green.paint("✔")
+            );
+            return;
+        }
+        let dotfolder = expand_user_path(&self.config.dotfolder_path);
+        for path in &untracked {
+            let relative = path
+                .strip_prefix(&dotfolder)
+                .expect("Failed to strip prefix");
+            println!("{} {}", Yellow.paint("??"), relative.display());
+        }
+        println!();
+
+        let mut journal = match self.begin_journal("untracked") {
+            Ok(journal) => journal,
+            Err(e) => {
+                println!("{} {}", Red.paint("✘"), e);
+                return;
+            }
+        };
+        for path in &untracked {
+            let relative = path
+                .strip_prefix(&dotfolder)
+                .expect("Failed to strip prefix");
+            // Leave everything as is when there is no terminal to ask in
+            let choice = Select::new()
+                .with_prompt(format!("{}", relative.display()))
+                .items(&["Track it", "Delete it", "Leave it"])
+                .default(2)
+                .interact()
+                .unwrap_or(2);
+            match choice {
+                0 => match self.config.add_path(path.to_string_lossy().to_string()) {
+                    Ok(()) => println!("{} Tracking ~/{}", Green.paint("✔"), relative.display()),
+                    Err(e) => println!("{} {}", Red.paint("✘"), e),
+                },
+                1 => match journal.delete(path) {
+                    Ok(()) => println!("{} Deleted {}", Green.paint("✔"), relative.display()),
+                    Err(e) => println!("{} {}: {}", Red.paint("✘"), relative.display(), e),
+                },
+                _ => {}
+            }
+        }
+        journal.finish();
+    }
+
+    pub fn status(&self) {
+        let config_paths = self.config.tracked_paths();
+        let linked_paths = self.current_state.linked_paths();
+        let paths_tobe_removed = Self::find_paths_to_removed(&linked_paths, &config_paths);
+        let mut paths_to_be_added = Self::find_paths_to_be_added(&linked_paths, &config_paths);
+        paths_to_be_added.sort_by_key(|p| config_paths.iter().position(|c| c == p));
+
+        for path in &paths_to_be_added {
+            let reason = match self.current_state.get(path).map(|record| record.outcome) {
+                Some(Outcome::Skipped) => " (skipped on last sync)",
+                Some(Outcome::Failed) => " (failed on last sync)",
+                Some(Outcome::Missing) => " (missing on last sync)",
+                _ => "",
+            };
+            println!("{} {}{}", Green.paint("++"), path, Fixed(8).paint(reason));
+        }
+        for path in &paths_tobe_removed {
+            println!("{} {}", Red.paint("--"), path);
+        }
+        for (entry, reason) in self.config.excluded_entries() {
+            println!(
+                "{} {}{}",
+                Fixed(8).paint("//"),
+                entry.path,
+                Fixed(8).paint(format!(" (excluded: {})", reason))
+            );
+        }
+
+        // Linked paths whose link changed since, or no longer matches the config
+        for entry in self.config.active_entries() {
+            let Some(record) = self.current_state.get(&entry.path) else {
+                continue;
+            };
+            if record.outcome != Outcome::Linked {
+                continue;
+            }
+            let (home, dot) = get_home_and_dot_path(&entry.path);
+            let mode = self.deploy_mode(entry, &home, &dot);
+            let changed = if record.mode == Some(DeployMode::Copy) {
+                (!same_content(&home, &dot)).then_some(" (copy differs from the dotfolder)")
+            } else if record.mode == Some(DeployMode::FileLinks) {
+                let linked = is_real_dir(&home)
+                    && self
+                        .file_health(entry, &home, &dot)
+                        .iter()
+                        .all(|(_, label, _)| *label == "[LINKED]");
+                (!linked).then_some(" (some files are no longer linked)")
+            } else {
+                (!is_link_to(&home, &dot)).then_some(" (no longer linked to the dotfolder)")
+            };
+            if let Some(changed) = changed {
+                println!(
+                    "{} {}{}",
+                    Yellow.paint("!!"),
+                    entry.path,
+                    Fixed(8).paint(changed)
+                );
+            } else if record.mode.is_some_and(|recorded| recorded != mode) {
+                let reason = if mode == DeployMode::Copy || record.mode == Some(DeployMode::Copy) {
+                    " (mode changed)"
+                } else if mode == DeployMode::FileLinks
+                    || record.mode == Some(DeployMode::FileLinks)
+                {
+                    " (granularity changed)"
+                } else {
+                    " (link style changed)"
+                };
+                println!(
+                    "{} {}{}",
+                    Yellow.paint("~~"),
+                    entry.path,
+                    Fixed(8).paint(reason)
+                );
+            }
+        }
+    }
+
+    /// Prints every tracked path with how it is deployed and its duplicate and delink policies.
+    /// Settings inherited from `[defaults]` are dimmed.
+    pub fn list(&self) {
+        let width = self
+            .config
+            .paths
             .iter()
-            .for_each(|p| println!("{} {}", Red.paint("--"), p));
+            .map(|entry| entry.path.len())
+            .max()
+            .unwrap_or_default();
+        let setting = |value: String, own: bool| {
+            if own {
+                value
+            } else {
+                Fixed(8).paint(value).to_string()
+            }
+        };
+
+        for entry in &self.config.paths {
+            let link_style = self.config.link_style_for(entry).as_str();
+            let deploy = match self.config.deploy_mode_for(entry) {
+                DeployMode::Copy => setting(format!("{:<14}", "copy"), true),
+                DeployMode::FileLinks => {
+                    setting(format!("{:<14}", format!("files {}", link_style)), true)
+                }
+                _ => setting(
+                    format!("{:<14}", format!("link {}", link_style)),
+                    entry.link_style.is_some(),
+                ),
+            };
+            let on_duplicate = setting(
+                format!(
+                    "on_duplicate = {:<16}",
+                    self.config.on_duplicate_for(entry).as_str()
+                ),
+                entry.on_duplicate.is_some(),
+            );
+            let on_delink = setting(
+                format!(
+                    "on_delink = {:<6}",
+                    self.config.on_delink_for(entry).as_str()
+                ),
+                entry.on_delink.is_some(),
+            );
+            print!(
+                "{:<width$}  {} {} {}",
+                entry.path, deploy, on_duplicate, on_delink
+            );
+            if let Some(reason) = entry.excluded_because() {
+                print!(" {}", Yellow.paint(format!("(excluded: {})", reason)));
+            }
+            if let Some(note) = &entry.note {
+                print!(" {}", Fixed(8).paint(format!("# {}", note)));
+            }
+            println!();
+        }
     }
 
     pub fn check(&self) {
-        self.config.paths.iter().for_each(|path| {
+        self.config.paths.iter().for_each(|entry| {
+            let path = &entry.path;
+            if let Some(reason) = entry.excluded_because() {
+                println!(
+                    "{:<13} {} {}",
+                    Fixed(8).paint("[EXCLUDED]"),
+                    path,
+                    Fixed(8).paint(format!("({})", reason))
+                );
+                return;
+            }
             let (home, dot) = get_home_and_dot_path(path);
+            let mode = self.deploy_mode(entry, &home, &dot);
+            if mode == DeployMode::FileLinks && !home.is_symlink() {
+                self.check_files(entry, &home, &dot);
+                return;
+            }
 
+            let copy = mode == DeployMode::Copy;
             let (label, color) = if home.is_symlink() {
-                match home.canonicalize() {
-                    Ok(target) => {
-                        if target == dot {
-                            ("[LINKED]", Green)
-                        } else {
-                            ("[WRONG-TGT]", Red)
-                        }
+                if is_link_to(&home, &dot) {
+                    if mode != DeployMode::FileLinks
+                        && !copy
+                        && get_link_style(&home) == Some(self.config.link_style_for(entry))
+                    {
+                        ("[LINKED]", Green)
+                    } else {
+                        ("[LINK-STYLE]", Yellow)
                     }
-                    Err(_) => ("[BROKEN-LNK]", Red),
+                } else if home.exists() {
+                    ("[WRONG-TGT]", Red)
+                } else {
+                    ("[BROKEN-LNK]", Red)
                 }
             } else {
                 let dot_exists = dot.exists();
@@ -276,6 +1529,10 @@ impl DotManager {
                     (true, true) => {
                         if dot.is_dir() != home.is_dir() {
                             ("[TYPE-MISM]", Yellow)
+                        } else if copy && same_content(&home, &dot) {
+                            ("[COPIED]", Green)
+                        } else if copy {
+                            ("[MODIFIED]", Yellow)
                         } else {
                             ("[DISABLED]", Blue)
                         }
@@ -288,4 +1545,19 @@ impl DotManager {
             println!("{:<13} {}", color.paint(label), path);
         });
     }
-}
\ No newline at end of file
+    /// Prints a `granularity = "files"` folder, then the health of each of its files.
+    fn check_files(&self, entry: &PathEntry, home: &Path, dot: &Path) {
+        let files = self.file_health(entry, home, dot);
+        let (label, color) = if !home.exists() && !dot.exists() {
+            ("[BOTH-MISS]", Fixed(8))
+        } else if files.iter().all(|(_, label, _)| *label == "[LINKED]") {
+            ("[LINKED]", Green)
+        } else {
+            ("[PARTIAL]", Yellow)
+        };
+        println!("{:<13} {}", color.paint(label), entry.path);
+        for (home_file, label, color) in files {
+            println!("{:<13}   {}", color.paint(label), contract_path(&home_file));
+        }
+    }
+}
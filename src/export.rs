@@ -0,0 +1,187 @@
+use crate::args::ConfigFormat;
+use crate::config::{Config, LinkStyle};
+use crate::utils::{
+    contract_path, copy_all, expand_path, expand_user_path, get_home_and_dot_path, get_home_dir,
+    get_link_target,
+};
+use ansi_term::Colour::*;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::fs;
+use std::fs::File;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+/// Copies every tracked path from the dotfolder into `out`, laid out as in home and as real
+/// files, as done by `lazydot export`. An `out` ending in `.tar.gz` or `.tgz` is written as a
+/// tarball instead. Home itself is never read or changed.
+pub fn export(config: &Config, out: &Path) -> Result<usize, String> {
+    check_out(out)?;
+
+    let files = files_to_export(config);
+    let name = out.to_string_lossy();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        write_tarball(&files, out)?;
+    } else {
+        for (relative, path_in_dotfolder) in &files {
+            copy_all(path_in_dotfolder, &out.join(relative))
+                .map_err(|e| format!("Failed to copy {}: {}", path_in_dotfolder.display(), e))?;
+        }
+    }
+    Ok(files.len())
+}
+
+/// Writes the definition of another dotfile manager that links the tracked paths to the
+/// dotfolder, as done by `lazydot export-config`, so it can be shared with their users.
+pub fn export_config(config: &Config, format: ConfigFormat, out: &Path) -> Result<usize, String> {
+    check_out(out)?;
+    let files = files_to_export(config);
+    match format {
+        ConfigFormat::Stow => write_stow_package(&files, out)?,
+        ConfigFormat::HomeManager => {
+            let dotfolder = dotfolder_reference(config, "${config.home.homeDirectory}");
+            write_file(out, &home_manager_module(&files, &dotfolder))?
+        }
+        ConfigFormat::Chezmoi => {
+            let dotfolder = dotfolder_reference(config, "{{ .chezmoi.homeDir }}");
+            for (relative, _) in &files {
+                let target = format!("{}/{}\n", dotfolder, relative.display());
+                write_file(&out.join(chezmoi_source_name(relative)), &target)?;
+            }
+        }
+    }
+    Ok(files.len())
+}
+
+/// An existing non-empty folder or a file is never written into.
+fn check_out(out: &Path) -> Result<(), String> {
+    if out.exists() && (!out.is_dir() || out.read_dir().map_err(|e| e.to_string())?.count() > 0) {
+        return Err(format!("{} already exists", out.display()));
+    }
+    Ok(())
+}
+
+/// The dotfolder as the other manager writes it, with `home` standing for `~`.
+fn dotfolder_reference(config: &Config, home: &str) -> String {
+    let dotfolder = contract_path(&expand_user_path(&config.dotfolder_path));
+    match dotfolder.strip_prefix("~/") {
+        Some(relative) => format!("{}/{}", home, relative),
+        None => dotfolder,
+    }
+}
+
+/// A relative link per tracked path, for `stow -d <parent of out> <name of out>`.
+fn write_stow_package(files: &[(PathBuf, PathBuf)], out: &Path) -> Result<(), String> {
+    for (relative, path_in_dotfolder) in files {
+        let link = out.join(relative);
+        let parent = link.parent().expect("Failed to get the parent folder");
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        let target = get_link_target(path_in_dotfolder, &link, LinkStyle::Relative);
+        symlink(&target, &link).map_err(|e| format!("Failed to link {}: {}", link.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Out-of-store links keep the files editable in the dotfolder, as LazyDot links do.
+fn home_manager_module(files: &[(PathBuf, PathBuf)], dotfolder: &str) -> String {
+    let mut module = String::from(
+        "# Generated by `lazydot export-config`\n{ config, ... }:\n\n{\n  home.file = {\n",
+    );
+    for (relative, _) in files {
+        module.push_str(&format!(
+            "    \"{0}\".source = config.lib.file.mkOutOfStoreSymlink \"{1}/{0}\";\n",
+            relative.display(),
+            dotfolder
+        ));
+    }
+    module.push_str("  };\n}\n");
+    module
+}
+
+/// `.config/nvim` becomes `dot_config/symlink_nvim.tmpl`, a template holding the link target.
+fn chezmoi_source_name(relative: &Path) -> PathBuf {
+    let mut components: Vec<String> = relative
+        .iter()
+        .map(|component| {
+            let name = component.to_string_lossy();
+            match name.strip_prefix('.') {
+                Some(rest) => format!("dot_{}", rest),
+                None => name.to_string(),
+            }
+        })
+        .collect();
+    if let Some(last) = components.last_mut() {
+        *last = format!("symlink_{}.tmpl", last);
+    }
+    components.iter().collect()
+}
+
+fn write_file(path: &Path, content: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Home-relative destination and dotfolder source of each tracked path that can be exported.
+/// Paths whose `when` conditions don't hold on this machine are left out, as sync does.
+fn files_to_export(config: &Config) -> Vec<(PathBuf, PathBuf)> {
+    for (entry, reason) in config.excluded_entries() {
+        println!(
+            "{} {} is excluded ({}), skipping",
+            Yellow.paint("!"),
+            entry.path,
+            reason
+        );
+    }
+    let mut files = Vec::new();
+    for entry in config.active_entries() {
+        let path_in_home = expand_path(&entry.path);
+        let Ok(relative) = path_in_home.strip_prefix(get_home_dir()) else {
+            println!(
+                "{} {} is outside home, skipping",
+                Yellow.paint("!"),
+                entry.path
+            );
+            continue;
+        };
+        let (_, path_in_dotfolder) = get_home_and_dot_path(&entry.path);
+        if !path_in_dotfolder.exists() {
+            println!(
+                "{} {} is not in the dotfolder, skipping",
+                Yellow.paint("!"),
+                entry.path
+            );
+            continue;
+        }
+        println!("{} {}", Green.paint("✔"), entry.path);
+        files.push((PathBuf::from(relative), path_in_dotfolder));
+    }
+    files
+}
+
+fn write_tarball(files: &[(PathBuf, PathBuf)], out: &Path) -> Result<(), String> {
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let file =
+        File::create(out).map_err(|e| format!("Failed to create {}: {}", out.display(), e))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    builder.follow_symlinks(true);
+    for (relative, path_in_dotfolder) in files {
+        let result = if path_in_dotfolder.is_dir() {
+            builder.append_dir_all(relative, path_in_dotfolder)
+        } else {
+            builder.append_path_with_name(path_in_dotfolder, relative)
+        };
+        result.map_err(|e| format!("Failed to add {}: {}", path_in_dotfolder.display(), e))?;
+    }
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| format!("Failed to write {}: {}", out.display(), e))?;
+    Ok(())
+}
@@ -0,0 +1,284 @@
+use crate::dot_manager::DotManager;
+use crate::journal::Journal;
+use crate::utils::{expand_path, expand_user_path, get_home_dir, get_link_target, is_link_to};
+use ansi_term::Colour::*;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Used by stow when a package has no `.stow-local-ignore`.
+const STOW_DEFAULT_IGNORES: [&str; 16] = [
+    "RCS",
+    ".+,v",
+    "CVS",
+    "\\.\\#.+",
+    "\\.cvsignore",
+    "\\.svn",
+    "_darcs",
+    "\\.hg",
+    "\\.git",
+    "\\.gitignore",
+    "\\.gitmodules",
+    ".+~",
+    "\\#.*\\#",
+    "^/README.*",
+    "^/LICENSE.*",
+    "^/COPYING",
+];
+
+/// A path found in a stow package, at the level stow links it in home.
+#[derive(Debug, PartialEq)]
+pub struct StowEntry {
+    pub package: String,
+    /// Path relative to home, and to the package.
+    pub relative: PathBuf,
+    pub status: StowStatus,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StowStatus {
+    /// Linked in home by stow.
+    Stowed,
+    /// Not in home yet, sync will link it.
+    Unstowed,
+    /// Something else is in the way, left alone.
+    Conflict(String),
+}
+
+impl StowEntry {
+    pub fn path(&self) -> String {
+        format!("~/{}", self.relative.display())
+    }
+}
+
+/// Ignore patterns of a stow package: Perl regexes matched against the file name, or against
+/// the path from the package root when they contain a `/`.
+struct StowIgnore {
+    names: Vec<Regex>,
+    paths: Vec<Regex>,
+}
+
+impl StowIgnore {
+    fn load(package: &Path) -> Result<StowIgnore, String> {
+        let content = fs::read_to_string(package.join(".stow-local-ignore")).ok();
+        let lines: Vec<String> = match &content {
+            Some(content) => content
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.to_string())
+                .collect(),
+            None => STOW_DEFAULT_IGNORES.iter().map(|p| p.to_string()).collect(),
+        };
+        let mut ignore = StowIgnore {
+            names: Vec::new(),
+            paths: Vec::new(),
+        };
+        for line in lines {
+            let invalid = |e| format!("Invalid pattern {} in {}: {}", line, package.display(), e);
+            if line.contains('/') {
+                ignore
+                    .paths
+                    .push(Regex::new(&format!("(?:{})$", line)).map_err(invalid)?);
+            } else {
+                ignore
+                    .names
+                    .push(Regex::new(&format!("^(?:{})$", line)).map_err(invalid)?);
+            }
+        }
+        // The ignore file itself is never stowed
+        ignore
+            .names
+            .push(Regex::new("^\\.stow-local-ignore$").expect("Invalid pattern"));
+        Ok(ignore)
+    }
+
+    fn is_ignored(&self, relative: &Path) -> bool {
+        let name = relative
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let path = format!("/{}", relative.display());
+        self.names.iter().any(|regex| regex.is_match(&name))
+            || self.paths.iter().any(|regex| regex.is_match(&path))
+    }
+}
+
+/// Walks the stow `packages` (all of them when empty) of `stow_dir` alongside home and lists
+/// what `lazydot import stow` would track.
+pub fn plan_stow(stow_dir: &Path, packages: &[String]) -> Result<Vec<StowEntry>, String> {
+    if !stow_dir.is_dir() {
+        return Err(format!("{} is not a directory", stow_dir.display()));
+    }
+    let packages = if packages.is_empty() {
+        let mut packages: Vec<String> = fs::read_dir(stow_dir)
+            .map_err(|e| format!("Failed to read {}: {}", stow_dir.display(), e))?
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| !name.starts_with('.'))
+            .collect();
+        packages.sort();
+        packages
+    } else {
+        packages.to_vec()
+    };
+
+    let mut entries = Vec::new();
+    for package in packages {
+        let package_dir = stow_dir.join(&package);
+        if !package_dir.is_dir() {
+            return Err(format!("No package {} in {}", package, stow_dir.display()));
+        }
+        let ignore = StowIgnore::load(&package_dir)?;
+        walk_package(&package, &package_dir, Path::new(""), &ignore, &mut entries)?;
+    }
+    Ok(entries)
+}
+
+fn walk_package(
+    package: &str,
+    package_dir: &Path,
+    relative: &Path,
+    ignore: &StowIgnore,
+    entries: &mut Vec<StowEntry>,
+) -> Result<(), String> {
+    let dir = package_dir.join(relative);
+    let mut children: Vec<PathBuf> = fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        .flatten()
+        .map(|entry| relative.join(entry.file_name()))
+        .collect();
+    children.sort();
+
+    for child in children {
+        if ignore.is_ignored(&child) {
+            continue;
+        }
+        let source = package_dir.join(&child);
+        let path_in_home = get_home_dir().join(&child);
+        let status = if path_in_home.is_symlink() {
+            if is_link_to(&path_in_home, &source) {
+                StowStatus::Stowed
+            } else {
+                StowStatus::Conflict("links somewhere else".to_string())
+            }
+        } else if path_in_home.is_dir() && source.is_dir() && !source.is_symlink() {
+            // Stow links inside folders that already exist in home
+            walk_package(package, package_dir, &child, ignore, entries)?;
+            continue;
+        } else if path_in_home.exists() {
+            StowStatus::Conflict("exists in home".to_string())
+        } else {
+            StowStatus::Unstowed
+        };
+        entries.push(StowEntry {
+            package: package.to_string(),
+            relative: child,
+            status,
+        });
+    }
+    Ok(())
+}
+
+pub fn print_stow_plan(entries: &[StowEntry]) {
+    for entry in entries {
+        let (label, color, note) = match &entry.status {
+            StowStatus::Stowed => ("[RELINK]", Blue, "stowed".to_string()),
+            StowStatus::Unstowed => ("[TRACK]", Blue, "not linked yet".to_string()),
+            StowStatus::Conflict(reason) => ("[SKIP]", Yellow, reason.clone()),
+        };
+        println!(
+            "{:<13} {} {}",
+            color.paint(label),
+            entry.path(),
+            Fixed(8).paint(format!("({}: {})", entry.package, note))
+        );
+    }
+}
+
+/// Moves the content of stow packages into the dotfolder, replaces the stow links with
+/// LazyDot links and tracks every imported path, as done by `lazydot import stow`.
+pub fn import_stow(
+    manager: &mut DotManager,
+    stow_dir: &str,
+    packages: &[String],
+    dry_run: bool,
+) -> Result<(), String> {
+    let stow_dir = expand_path(stow_dir);
+    let entries = plan_stow(&stow_dir, packages)?;
+    if entries.is_empty() {
+        println!("{} Nothing to import", Yellow.paint("!"));
+        return Ok(());
+    }
+    print_stow_plan(&entries);
+    if dry_run {
+        return Ok(());
+    }
+    println!();
+
+    let mut journal = manager.begin_journal("import")?;
+    let imported = import_entries(manager, &stow_dir, &entries, &mut journal);
+    // Whatever moved before a failure can still be undone
+    journal.finish();
+    imported?;
+    println!("\nRun `lazydot sync` to link the paths stow hadn't linked yet.");
+    Ok(())
+}
+
+fn import_entries(
+    manager: &mut DotManager,
+    stow_dir: &Path,
+    entries: &[StowEntry],
+    journal: &mut Journal,
+) -> Result<(), String> {
+    let dotfolder = expand_user_path(&manager.config.dotfolder_path);
+    let link_style = manager.config.defaults.link_style;
+    for entry in entries {
+        if matches!(entry.status, StowStatus::Conflict(_)) {
+            continue;
+        }
+        let package_dir = stow_dir.join(&entry.package);
+        let source = package_dir.join(&entry.relative);
+        let path_in_dotfolder = dotfolder.join(&entry.relative);
+        let path_in_home = get_home_dir().join(&entry.relative);
+        if path_in_dotfolder.exists() {
+            println!(
+                "{} {} is already in the dotfolder, skipping",
+                Red.paint("✘"),
+                entry.path()
+            );
+            continue;
+        }
+
+        if entry.status == StowStatus::Stowed {
+            journal.unlink(&path_in_home);
+        }
+        journal
+            .move_path(&source, &path_in_dotfolder)
+            .map_err(|e| format!("Failed to move {}: {}", source.display(), e))?;
+        if entry.status == StowStatus::Stowed {
+            let link_target = get_link_target(&path_in_dotfolder, &path_in_home, link_style);
+            journal
+                .link(&link_target, &path_in_home)
+                .map_err(|e| format!("Failed to link {}: {}", entry.path(), e))?;
+        }
+        manager
+            .config
+            .add_path(path_in_dotfolder.to_string_lossy().to_string())?;
+        remove_empty_parents(&source, &package_dir);
+        println!("{} {}", Green.paint("✔"), entry.path());
+    }
+    Ok(())
+}
+
+/// Removes the folders left empty in a package once its content moved to the dotfolder.
+fn remove_empty_parents(moved: &Path, package_dir: &Path) {
+    let mut dir = moved.parent();
+    while let Some(current) = dir {
+        if !current.starts_with(package_dir) || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
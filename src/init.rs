@@ -0,0 +1,206 @@
+use crate::config::{Config, PathEntry};
+use crate::dot_manager::DotManager;
+use crate::utils::{contract_path, expand_path, expand_user_path, get_home_dir};
+use ansi_term::Colour::*;
+use dialoguer::{Confirm, MultiSelect};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Dotfiles offered by `lazydot init` when they exist in home.
+const COMMON_DOTFILES: [&str; 14] = [
+    "~/.bashrc",
+    "~/.bash_profile",
+    "~/.profile",
+    "~/.zshrc",
+    "~/.vimrc",
+    "~/.gitconfig",
+    "~/.tmux.conf",
+    "~/.inputrc",
+    "~/.config/nvim",
+    "~/.config/fish",
+    "~/.config/alacritty",
+    "~/.config/kitty",
+    "~/.config/starship.toml",
+    "~/.config/hypr",
+];
+
+const GITIGNORE_ENTRY: &str = "current_state.toml";
+
+/// Creates the dotfolder and writes a config pointing to it, as done by `lazydot init`.
+pub fn init(dotfolder: &str, git: bool) -> Result<Config, String> {
+    let mut config = Config::new();
+    if config.config_file.exists() {
+        return Err(format!(
+            "{} already exists, edit it or pass --config to create another one",
+            config.config_file.display()
+        ));
+    }
+
+    let dotfolder_path = expand_user_path(dotfolder);
+    if get_home_dir().starts_with(&dotfolder_path) {
+        return Err(format!("{} holds the home directory", dotfolder));
+    }
+    if dotfolder_path.exists() && !dotfolder_path.is_dir() {
+        return Err(format!("{} is not a directory", dotfolder_path.display()));
+    }
+    fs::create_dir_all(&dotfolder_path)
+        .map_err(|e| format!("Failed to create {}: {}", dotfolder_path.display(), e))?;
+    println!("{} Created {}", Green.paint("✔"), dotfolder_path.display());
+
+    // Track the config itself at the place it was actually written to
+    config.dotfolder_path = contract_path(&dotfolder_path);
+    config.paths = match config.config_file.strip_prefix(get_home_dir()) {
+        Ok(config_file) => vec![PathEntry::new(format!("~/{}", config_file.display()))],
+        Err(_) => Vec::new(),
+    };
+    config.save();
+    println!(
+        "{} Wrote {}",
+        Green.paint("✔"),
+        config.config_file.display()
+    );
+
+    write_gitignore(&dotfolder_path)?;
+    if git {
+        git_init(&dotfolder_path)?;
+    }
+    Ok(config)
+}
+
+/// Makes sure the dotfolder's `.gitignore` excludes the machine-local state file.
+fn write_gitignore(dotfolder_path: &Path) -> Result<(), String> {
+    let gitignore = dotfolder_path.join(".gitignore");
+    let mut content = fs::read_to_string(&gitignore).unwrap_or_default();
+    if content.lines().any(|line| line.trim() == GITIGNORE_ENTRY) {
+        return Ok(());
+    }
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(GITIGNORE_ENTRY);
+    content.push('\n');
+    fs::write(&gitignore, content).map_err(|e| format!("Failed to write .gitignore: {}", e))?;
+    println!("{} Wrote {}", Green.paint("✔"), gitignore.display());
+    Ok(())
+}
+
+fn git_init(dotfolder_path: &Path) -> Result<(), String> {
+    if dotfolder_path.join(".git").exists() {
+        println!("{} Already a git repository", Yellow.paint("!"));
+        return Ok(());
+    }
+    let status = Command::new("git")
+        .arg("init")
+        .arg("--quiet")
+        .current_dir(dotfolder_path)
+        .status()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !status.success() {
+        return Err("git init failed".to_string());
+    }
+    println!("{} Initialized a git repository", Green.paint("✔"));
+    Ok(())
+}
+
+/// Common dotfiles that exist in home and aren't tracked yet.
+pub fn find_common_dotfiles(config: &Config) -> Vec<String> {
+    COMMON_DOTFILES
+        .iter()
+        .filter(|path| {
+            let path_in_home = expand_path(path);
+            path_in_home.exists() && !path_in_home.is_symlink()
+        })
+        .filter(|path| !config.paths.iter().any(|entry| entry.path == **path))
+        .map(|path| path.to_string())
+        .collect()
+}
+
+/// Lets the user pick common dotfiles to track right away.
+pub fn offer_common_dotfiles(config: &mut Config) {
+    let candidates = find_common_dotfiles(config);
+    if candidates.is_empty() {
+        return;
+    }
+    println!(
+        "\n{}",
+        Yellow.paint("Found some common dotfiles. Select the ones to track:")
+    );
+    // Skip quietly when there is no terminal to ask in
+    let selected = MultiSelect::new()
+        .items(&candidates)
+        .interact()
+        .unwrap_or_default();
+
+    for index in selected {
+        let path = &candidates[index];
+        match config.add_path(path.clone()) {
+            Ok(()) => println!("{} {}", Green.paint("✔"), path),
+            Err(e) => println!("{} {}", Red.paint("✘"), e),
+        }
+    }
+}
+
+/// Finds the lazydot.toml stored inside a cloned dotfolder and links it to the global
+/// config location.
+pub fn link_repo_config(dotfolder: &str) -> Result<Config, String> {
+    let dotfolder_path = expand_user_path(dotfolder);
+    if !dotfolder_path.is_dir() {
+        return Err(format!("{} is not a directory", dotfolder_path.display()));
+    }
+
+    // The config is tracked like any other dotfile, so look where sync would have put it
+    let mut candidates = vec![dotfolder_path.join("lazydot.toml")];
+    if let Ok(relative) = Config::global_config_path().strip_prefix(get_home_dir()) {
+        candidates.insert(0, dotfolder_path.join(relative));
+    }
+    let repo_config = candidates
+        .into_iter()
+        .find(|path| path.is_file())
+        .ok_or(format!(
+            "No lazydot.toml found in {}",
+            dotfolder_path.display()
+        ))?;
+
+    let config = Config::load(&repo_config);
+    if expand_user_path(&config.dotfolder_path).canonicalize().ok()
+        != dotfolder_path.canonicalize().ok()
+    {
+        return Err(format!(
+            "{} expects the dotfolder at {}, clone it there instead",
+            repo_config.display(),
+            config.dotfolder_path
+        ));
+    }
+
+    let global_config = Config::link_global(&repo_config, false)?;
+    println!(
+        "{} {} -> {}",
+        Green.paint("✔"),
+        global_config.display(),
+        repo_config.display()
+    );
+    Ok(Config::load(&global_config))
+}
+
+/// Sets up a new machine from a cloned dotfolder, as done by `lazydot bootstrap`.
+pub fn bootstrap(dotfolder: &str, yes: bool) -> Result<(), String> {
+    link_repo_config(dotfolder)?;
+
+    let manager = DotManager::new();
+    println!("\n{}", Blue.paint("Sync will do the following:"));
+    manager.print_plan();
+
+    if !yes {
+        let confirmed = Confirm::new()
+            .with_prompt("Continue?")
+            .default(true)
+            .interact()
+            .unwrap_or(false);
+        if !confirmed {
+            return Ok(());
+        }
+    }
+    manager.sync();
+    Ok(())
+}
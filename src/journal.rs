@@ -0,0 +1,255 @@
+use crate::current_state::CurrentState;
+use crate::privileged::Privileges;
+use crate::utils::delete;
+use ansi_term::Colour::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const JOURNAL_FILE: &str = "journal.toml";
+const BACKUP_DIR: &str = "backup";
+
+/// A single filesystem change made by a journaled command such as `sync` or `disable-link`.
+/// Every variant carries enough information to be reverted by `undo`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    /// A symlink was created at `path`.
+    CreatedLink { path: PathBuf },
+    /// The symlink at `path` pointing to `target` was removed.
+    RemovedLink { path: PathBuf, target: PathBuf },
+    /// `from` was copied to `to` and then deleted.
+    Moved { from: PathBuf, to: PathBuf },
+    /// `to` was created as a copy of `from`.
+    Copied { from: PathBuf, to: PathBuf },
+    /// `from` was renamed to `to`.
+    Renamed { from: PathBuf, to: PathBuf },
+    /// `path` was deleted, its content is kept under the journal backup folder.
+    Deleted { path: PathBuf, backup: String },
+}
+
+/// Record of the last `sync`, `disable-link`, `untracked`, `gc` or `import stow` run, used by
+/// `lazydot undo`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Journal {
+    pub command: String,
+    pub previous_state: CurrentState,
+    pub operations: Vec<Operation>,
+    /// Escalation used for paths outside home, reused when undoing.
+    #[serde(default)]
+    pub privileges: Privileges,
+
+    /// State folder of the dotfolder this journal belongs to.
+    #[serde(skip)]
+    state_dir: PathBuf,
+}
+
+impl Journal {
+    /// Starts recording a new operation. Backups are written to a pending folder
+    /// so the previous journal stays usable until this one is finished. A run that was
+    /// interrupted before finishing is kept as the journal to undo, and this one isn't started.
+    pub fn begin(
+        command: &str,
+        previous_state: &CurrentState,
+        state_dir: &Path,
+        privileges: Privileges,
+    ) -> Result<Journal, String> {
+        let journal = Journal {
+            command: command.to_string(),
+            previous_state: previous_state.clone(),
+            operations: Vec::new(),
+            privileges,
+            state_dir: state_dir.to_path_buf(),
+        };
+        let pending = journal.pending_dir();
+        if let Some(interrupted) = Self::read(&pending, state_dir) {
+            let message = format!(
+                "The last `{}` was interrupted, run `lazydot undo` to revert what it did, or run this command again to keep it",
+                interrupted.command
+            );
+            interrupted.finish();
+            return Err(message);
+        }
+        // Without a record, nothing was changed before the backup in it was taken
+        if pending.exists() {
+            delete(&pending);
+        }
+        Ok(journal)
+    }
+
+    /// Persists the journal, replacing the previous one. Runs that changed nothing are dropped.
+    pub fn finish(self) {
+        let pending = self.pending_dir();
+        if self.operations.is_empty() {
+            if pending.exists() {
+                delete(&pending);
+            }
+            return;
+        }
+        self.write_pending();
+
+        let dir = self.dir();
+        if dir.exists() {
+            delete(&dir);
+        }
+        fs::rename(&pending, &dir).expect("Failed to save journal");
+    }
+
+    /// Loads the journal of the last run, if there is one.
+    pub fn load(state_dir: &Path) -> Option<Journal> {
+        Self::read(&state_dir.join("last_operation"), state_dir)
+    }
+
+    fn read(dir: &Path, state_dir: &Path) -> Option<Journal> {
+        let path = dir.join(JOURNAL_FILE);
+        if !path.exists() {
+            return None;
+        }
+        let contents = fs::read_to_string(&path).expect("Failed to read journal");
+        let mut journal: Journal = toml::from_str(&contents).expect("Failed to parse journal");
+        journal.state_dir = state_dir.to_path_buf();
+        Some(journal)
+    }
+
+    /// Keeps the operations recorded so far on disk, so an interrupted run can still be undone.
+    fn record(&mut self, operation: Operation) {
+        self.operations.push(operation);
+        self.write_pending();
+    }
+
+    fn write_pending(&self) {
+        let pending = self.pending_dir();
+        fs::create_dir_all(&pending).expect("Failed to create the journal folder");
+        let toml_string = toml::to_string_pretty(self).expect("Failed to serialize journal");
+        fs::write(pending.join(JOURNAL_FILE), toml_string).expect("Failed to write journal");
+    }
+
+    /// Removes the journal once it has been undone.
+    pub fn discard(&self) {
+        let dir = self.dir();
+        if dir.exists() {
+            delete(&dir);
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.state_dir.join("last_operation")
+    }
+
+    fn pending_dir(&self) -> PathBuf {
+        self.state_dir.join("last_operation.pending")
+    }
+
+    pub fn link(&mut self, target: &Path, path: &Path) -> io::Result<()> {
+        self.privileges.symlink(target, path)?;
+        self.record(Operation::CreatedLink {
+            path: path.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    pub fn unlink(&mut self, path: &Path) {
+        let target = fs::read_link(path).expect("Failed to read symlink");
+        self.privileges
+            .remove(path)
+            .expect("Failed to remove symlink");
+        self.record(Operation::RemovedLink {
+            path: path.to_path_buf(),
+            target,
+        });
+    }
+
+    pub fn move_path(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        self.privileges.copy(from, to)?;
+        self.privileges.remove(from)?;
+        self.record(Operation::Moved {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    pub fn copy(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        self.privileges.copy(from, to)?;
+        self.record(Operation::Copied {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    pub fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        self.privileges.rename(from, to)?;
+        self.record(Operation::Renamed {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    /// Deletes `path` after keeping a copy of it in the journal. Symlinks are only unlinked.
+    /// Nothing is deleted when the copy can't be made.
+    pub fn delete(&mut self, path: &Path) -> io::Result<()> {
+        if path.is_symlink() {
+            self.unlink(path);
+            return Ok(());
+        }
+        let backup = self.operations.len().to_string();
+        self.privileges
+            .copy(path, &self.pending_dir().join(BACKUP_DIR).join(&backup))?;
+        // Recorded before deleting, so the backup is never left without its record
+        self.record(Operation::Deleted {
+            path: path.to_path_buf(),
+            backup,
+        });
+        self.privileges.remove(path)
+    }
+
+    /// Reverts every recorded operation, newest first. Fails when any of them couldn't be.
+    pub fn revert(&self) -> Result<(), String> {
+        let backup_dir = self.dir().join(BACKUP_DIR);
+        let privileges = &self.privileges;
+        let mut failed = 0;
+        for operation in self.operations.iter().rev() {
+            print!("{}", Yellow.paint("Reverting: "));
+            let (result, path) = match operation {
+                Operation::CreatedLink { path } => match path.is_symlink() {
+                    true => (privileges.remove(path), path),
+                    false => (Ok(()), path),
+                },
+                Operation::RemovedLink { path, target } => (privileges.symlink(target, path), path),
+                Operation::Moved { from, to } => {
+                    let result = privileges
+                        .copy(to, from)
+                        .and_then(|_| privileges.remove(to));
+                    (result, from)
+                }
+                Operation::Copied { to, .. } => match to.exists() || to.is_symlink() {
+                    true => (privileges.remove(to), to),
+                    false => (Ok(()), to),
+                },
+                Operation::Renamed { from, to } => (privileges.rename(to, from), from),
+                Operation::Deleted { path, backup } => {
+                    (privileges.copy(&backup_dir.join(backup), path), path)
+                }
+            };
+            match result {
+                Ok(()) => println!("{} {}", Green.paint("✔"), path.display()),
+                Err(e) => {
+                    println!("{} {}: {}", Red.paint("✘"), path.display(), e);
+                    failed += 1;
+                }
+            }
+        }
+        match failed {
+            0 => Ok(()),
+            _ => Err(format!(
+                "{} of {} changes couldn't be reverted, the undo record and its backups are kept in {}",
+                failed,
+                self.operations.len(),
+                self.dir().display()
+            )),
+        }
+    }
+}
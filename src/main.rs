@@ -1,22 +1,36 @@
 mod args;
 mod config;
 mod dot_manager;
+mod export;
+mod import;
 mod tests {
+    pub mod test_config;
     pub mod test_dot_manager;
+    pub mod test_export;
+    pub mod test_import;
+    pub mod test_init;
+    pub mod test_privileged;
+    pub mod test_relocate;
     pub mod test_utils;
 }
 mod create_toml_temp;
 mod current_state;
+mod init;
+mod journal;
+mod privileged;
+mod relocate;
 mod utils;
 
-use crate::args::Command;
+use crate::args::{Command, ConfigCommand, ImportCommand, StateCommand};
 use crate::config::OnDelinkBehavior;
 use crate::dot_manager::DotManager;
+use ansi_term::Colour::{Green, Red};
 use args::LazyDotsArgs;
 use clap::{CommandFactory, Parser};
-use clap_complete::{generate, Shell};
+use clap_complete::{Shell, generate};
 use config::Config;
 use std::io;
+use utils::expand_path;
 
 fn main() {
     let args = LazyDotsArgs::parse();
@@ -27,6 +41,17 @@ fn main() {
         generate(shell, &mut cmd, "lazydot", &mut io::stdout());
         return;
     }
+    if let Some(config_file) = &args.config {
+        Config::set_config_path(expand_path(config_file));
+    }
+    if let Some(home) = &args.home {
+        let home = expand_path(home);
+        if !home.is_dir() {
+            println!("{} {} is not a directory", Red.paint("✘"), home.display());
+            return;
+        }
+        utils::set_home_dir(Some(home));
+    }
 
     match args.command {
         Command::Add(add_args) => {
@@ -38,7 +63,9 @@ fn main() {
         Command::Remove(remove_args) => {
             let mut config = Config::new();
             for path in remove_args.paths {
-                config.remove_path(path);
+                if let Err(e) = config.remove_path(path) {
+                    println!("{} {}", Red.paint("✘"), e);
+                }
             }
         }
         Command::Sync(_apply_args) => {
@@ -53,7 +80,11 @@ fn main() {
             let mut manager = DotManager::new();
             match delink_args.all {
                 true => {
+                    // Disabling every link is temporary, the dotfolder copies all stay
                     manager.config.defaults.on_delink = OnDelinkBehavior::Keep;
+                    for entry in &mut manager.config.paths {
+                        entry.on_delink = None;
+                    }
                     manager.delink_all();
                 }
                 false => {
@@ -65,9 +96,92 @@ fn main() {
             let manager = DotManager::new();
             manager.status();
         }
+        Command::List(_) => {
+            let manager = DotManager::without_state();
+            manager.list();
+        }
         Command::Check(_) => {
             let manager = DotManager::new();
             manager.check();
         }
+        Command::Untracked(_) => {
+            let mut manager = DotManager::new();
+            manager.untracked();
+        }
+        Command::Gc(gc_args) => {
+            let manager = DotManager::new();
+            manager.gc(gc_args.depth);
+        }
+        Command::Undo(_) => {
+            let manager = DotManager::new();
+            manager.undo();
+        }
+        Command::Init(init_args) => match init::init(&init_args.dotfolder, init_args.git) {
+            Ok(mut config) => {
+                init::offer_common_dotfiles(&mut config);
+                println!("\nRun `lazydot sync` to link your dotfiles.");
+            }
+            Err(e) => println!("{} {}", Red.paint("✘"), e),
+        },
+        Command::Bootstrap(bootstrap_args) => {
+            if let Err(e) = init::bootstrap(&bootstrap_args.dotfolder, bootstrap_args.yes) {
+                println!("{} {}", Red.paint("✘"), e);
+            }
+        }
+        Command::Export(export_args) => {
+            let out = expand_path(&export_args.out);
+            match export::export(&Config::new(), &out) {
+                Ok(count) => println!(
+                    "{} Exported {} paths to {}",
+                    Green.paint("✔"),
+                    count,
+                    out.display()
+                ),
+                Err(e) => println!("{} {}", Red.paint("✘"), e),
+            }
+        }
+        Command::ExportConfig(export_args) => {
+            let out = expand_path(&export_args.out);
+            match export::export_config(&Config::new(), export_args.format, &out) {
+                Ok(count) => println!(
+                    "{} Wrote {} paths to {}",
+                    Green.paint("✔"),
+                    count,
+                    out.display()
+                ),
+                Err(e) => println!("{} {}", Red.paint("✘"), e),
+            }
+        }
+        Command::Import(ImportCommand::Stow(stow_args)) => {
+            let mut manager = DotManager::new();
+            if let Err(e) = import::import_stow(
+                &mut manager,
+                &stow_args.dir,
+                &stow_args.packages,
+                stow_args.dry_run,
+            ) {
+                println!("{} {}", Red.paint("✘"), e);
+            }
+        }
+        Command::Relocate(relocate_args) => {
+            if let Err(e) = relocate::relocate(&relocate_args.path) {
+                println!("{} {}", Red.paint("✘"), e);
+            }
+        }
+        Command::State(StateCommand::Rebuild(rebuild_args)) => {
+            let mut manager = DotManager::without_state();
+            manager.rebuild_state(rebuild_args.depth);
+        }
+        Command::Config(ConfigCommand::Link(link_args)) => {
+            match Config::link_global(&expand_path(&link_args.path), link_args.force) {
+                Ok(global_config) => println!(
+                    "{} {} -> {}",
+                    Green.paint("✔"),
+                    global_config.display(),
+                    link_args.path
+                ),
+                Err(e) => println!("{} {}", Red.paint("✘"), e),
+            }
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,116 @@
+use crate::utils::{copy_all, delete};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::os::unix::fs::{MetadataExt, symlink};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Folders whose content is changed through an escalation command such as `sudo`,
+/// built from the `[[roots]]` of the config that set `escalate`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Privileges {
+    pub roots: Vec<PrivilegedRoot>,
+    /// Folders inside a root that stay ours, such as home and the dotfolder.
+    pub except: Vec<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrivilegedRoot {
+    pub target: PathBuf,
+    pub command: String,
+}
+
+impl Privileges {
+    /// The escalation command to change `path` with, if it needs one.
+    pub fn command_for(&self, path: &Path) -> Option<&str> {
+        if self.except.iter().any(|dir| path.starts_with(dir)) {
+            return None;
+        }
+        self.roots
+            .iter()
+            .filter(|root| path.starts_with(&root.target))
+            .max_by_key(|root| root.target.components().count())
+            .map(|root| root.command.as_str())
+    }
+
+    /// Creates the symlink at `path` and the folders leading to it, as a fresh home lacks them.
+    pub fn symlink(&self, target: &Path, path: &Path) -> io::Result<()> {
+        let parent = path.parent().expect("Failed to get the parent folder");
+        match self.command_for(path) {
+            Some(command) => {
+                run(command, "mkdir", &[Path::new("-p"), parent])?;
+                run(command, "ln", &[Path::new("-s"), target, path])
+            }
+            None => {
+                fs::create_dir_all(parent)?;
+                symlink(target, path)
+            }
+        }
+    }
+
+    /// Deletes `path`, recursively for folders. Symlinks are only unlinked.
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        match self.command_for(path) {
+            Some(command) => run(command, "rm", &[Path::new("-rf"), path]),
+            None => {
+                delete(path);
+                Ok(())
+            }
+        }
+    }
+
+    /// Copies `from` to `to`. A copy made with escalation out of a root is handed back to
+    /// the owner of the folder it lands in.
+    pub fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let command = self.command_for(to).or(self.command_for(from));
+        let Some(command) = command else {
+            return copy_all(from, to);
+        };
+        let parent = to.parent().expect("Failed to get the parent folder");
+        // Folders are merged into `to` like copy_all does, not copied inside it
+        let (folder, source) = match from.is_dir() {
+            true => (to, from.join(".")),
+            false => (parent, from.to_path_buf()),
+        };
+        match self.command_for(to) {
+            Some(_) => run(command, "mkdir", &[Path::new("-p"), folder])?,
+            None => fs::create_dir_all(folder)?,
+        }
+        run(command, "cp", &[Path::new("-a"), &source, to])?;
+        if self.command_for(to).is_none() {
+            let owner = fs::metadata(parent)?;
+            let owner = format!("{}:{}", owner.uid(), owner.gid());
+            run(command, "chown", &[Path::new("-R"), Path::new(&owner), to])?;
+        }
+        Ok(())
+    }
+
+    pub fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        match self.command_for(to).or(self.command_for(from)) {
+            Some(command) => run(command, "mv", &[from, to]),
+            None => fs::rename(from, to),
+        }
+    }
+}
+
+/// Runs `program` through the escalation `command`, which may carry its own arguments
+/// (`sudo -n`).
+fn run(command: &str, program: &str, args: &[&Path]) -> io::Result<()> {
+    let mut parts = command.split_whitespace();
+    let escalate = parts
+        .next()
+        .ok_or(io::Error::other("The escalation command is empty"))?;
+    let status = Command::new(escalate)
+        .args(parts)
+        .arg(program)
+        .args(args)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "`{} {}` failed: {}",
+            command, program, status
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use crate::config::Config;
+use crate::current_state::CurrentState;
+use crate::dot_manager::DotManager;
+use crate::journal::Journal;
+use crate::privileged::Privileges;
+use crate::utils::{
+    contract_path, copy_all, delete, expand_path, expand_user_path, get_home_dir, get_link_style,
+    get_link_target, is_real_dir, list_files, resolve_link,
+};
+use ansi_term::Colour::*;
+use std::fs;
+use std::path::Path;
+
+/// Moves the dotfolder to `new_path` and re-points everything at it, as done by
+/// `lazydot relocate`. A dotfolder that was already moved there by hand is accepted as is.
+pub fn relocate(new_path: &str) -> Result<(), String> {
+    let new_dotfolder = expand_path(new_path);
+    fix_config_link(&Config::new().config_file, &new_dotfolder)?;
+    let mut config = Config::new();
+    let old_dotfolder = expand_user_path(&config.dotfolder_path);
+    if get_home_dir().starts_with(&new_dotfolder) {
+        return Err(format!("{} holds the home directory", new_path));
+    }
+    if new_dotfolder == old_dotfolder {
+        return Err(format!(
+            "{} is already the dotfolder",
+            new_dotfolder.display()
+        ));
+    }
+    if new_dotfolder.starts_with(&old_dotfolder) {
+        return Err(format!(
+            "{} is inside the current dotfolder",
+            new_dotfolder.display()
+        ));
+    }
+
+    match (old_dotfolder.is_dir(), new_dotfolder.exists()) {
+        (true, false) => {
+            if let Some(parent) = new_dotfolder.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            // Renaming fails across file systems, copy there instead
+            if fs::rename(&old_dotfolder, &new_dotfolder).is_err() {
+                copy_all(&old_dotfolder, &new_dotfolder)
+                    .map_err(|e| format!("Failed to move the dotfolder: {}", e))?;
+                delete(&old_dotfolder);
+            }
+            println!(
+                "{} Moved {} to {}",
+                Green.paint("✔"),
+                old_dotfolder.display(),
+                new_dotfolder.display()
+            );
+        }
+        (false, true) if new_dotfolder.is_dir() => println!(
+            "{} {} was already moved to {}",
+            Yellow.paint("!"),
+            old_dotfolder.display(),
+            new_dotfolder.display()
+        ),
+        (true, true) => {
+            return Err(format!(
+                "{} already exists, remove it or move the dotfolder there by hand",
+                new_dotfolder.display()
+            ));
+        }
+        _ => {
+            return Err(format!(
+                "Neither {} nor {} is a directory",
+                old_dotfolder.display(),
+                new_dotfolder.display()
+            ));
+        }
+    }
+
+    // The config itself may be linked into the dotfolder, so fix links before saving it
+    fix_config_link(&config.config_file, &new_dotfolder)?;
+    let old_state_dir = CurrentState::dir(&config);
+    let state = CurrentState::new(&config).unwrap_or_default();
+    let mut paths = config.tracked_paths();
+    paths.extend(state.linked_paths());
+    paths.sort();
+    paths.dedup();
+    let privileges = config.privileges();
+    for path in &paths {
+        let path = expand_path(path);
+        // A `granularity = "files"` folder holds a link per file
+        let links = if is_real_dir(&path) {
+            list_files(&path, &|_| false)
+                .iter()
+                .map(|relative| path.join(relative))
+                .collect()
+        } else {
+            vec![path]
+        };
+        for link in &links {
+            repoint_link(link, &old_dotfolder, &new_dotfolder, &privileges)?;
+        }
+    }
+
+    config.dotfolder_path = contract_path(&new_dotfolder);
+    move_state(&old_state_dir, &config)?;
+    config.save();
+    println!(
+        "{} dotfolder_path = \"{}\" in {}",
+        Green.paint("✔"),
+        config.dotfolder_path,
+        config.config_file.display()
+    );
+
+    println!();
+    DotManager::new().check();
+    Ok(())
+}
+
+/// A config linked into a dotfolder that was already moved dangles and can't be read.
+/// Its old dotfolder is the part of the link target whose rest exists under `new_dotfolder`.
+fn fix_config_link(config_file: &Path, new_dotfolder: &Path) -> Result<(), String> {
+    if !config_file.is_symlink() || config_file.exists() {
+        return Ok(());
+    }
+    let Some(target) = resolve_link(config_file) else {
+        return Ok(());
+    };
+    let old_dotfolder = target.ancestors().skip(1).find(|ancestor| {
+        target
+            .strip_prefix(ancestor)
+            .is_ok_and(|relative| new_dotfolder.join(relative).exists())
+    });
+    match old_dotfolder {
+        Some(old_dotfolder) => repoint_link(
+            config_file,
+            old_dotfolder,
+            new_dotfolder,
+            &Privileges::default(),
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Points the link at `path` to the same entry under `new_dotfolder`, keeping its link style.
+/// Anything not linked into `old_dotfolder` is left alone.
+fn repoint_link(
+    path: &Path,
+    old_dotfolder: &Path,
+    new_dotfolder: &Path,
+    privileges: &Privileges,
+) -> Result<(), String> {
+    let Some(target) = resolve_link(path) else {
+        return Ok(());
+    };
+    let Ok(relative) = target.strip_prefix(old_dotfolder) else {
+        return Ok(());
+    };
+    let style = get_link_style(path).unwrap_or_default();
+    let link_target = get_link_target(&new_dotfolder.join(relative), path, style);
+    privileges
+        .remove(path)
+        .and_then(|_| privileges.symlink(&link_target, path))
+        .map_err(|e| format!("Failed to re-create {}: {}", path.display(), e))?;
+    println!("{} {}", Green.paint("✔"), path.display());
+    Ok(())
+}
+
+/// Moves the state kept for the old dotfolder to the folder of the relocated one.
+/// The undo journal refers to the old location, so it is dropped.
+fn move_state(old_state_dir: &Path, config: &Config) -> Result<(), String> {
+    let new_state_dir = CurrentState::dir(config);
+    if old_state_dir.exists() && !new_state_dir.exists() {
+        fs::create_dir_all(
+            new_state_dir
+                .parent()
+                .expect("Failed to get the state folder"),
+        )
+        .map_err(|e| format!("Failed to create the state folder: {}", e))?;
+        fs::rename(old_state_dir, &new_state_dir)
+            .map_err(|e| format!("Failed to move the state: {}", e))?;
+    }
+    if let Some(journal) = Journal::load(&new_state_dir) {
+        journal.discard();
+    }
+
+    let Ok(mut state) = CurrentState::new(config) else {
+        return Ok(());
+    };
+    if state.paths.is_empty() {
+        return Ok(());
+    }
+    for record in &mut state.paths {
+        if record.target.is_some() {
+            record.target = fs::read_link(expand_path(&record.path)).ok();
+        }
+    }
+    state.write(config);
+    Ok(())
+}
@@ -0,0 +1,323 @@
+#[cfg(test)]
+mod test {
+    use crate::config::{
+        Condition, Config, DuplicateBehavior, Granularity, LinkStyle, PathEntry, PathMode,
+    };
+    use crate::utils::{expand_path, get_home_dir, reset_test_environment};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    #[serial_test::serial]
+    fn test_config_from_env_var() {
+        reset_test_environment();
+        let config_file = get_home_dir().join("elsewhere/lazydot.toml");
+        unsafe {
+            env::set_var("LAZYDOT_CONFIG", &config_file);
+        }
+        let mut config = Config::new();
+        config.add_path("~/.bashrc".to_string()).unwrap();
+        unsafe {
+            env::remove_var("LAZYDOT_CONFIG");
+        }
+
+        assert_eq!(config.config_file, config_file);
+        assert!(
+            fs::read_to_string(&config_file)
+                .unwrap()
+                .contains("~/.bashrc")
+        );
+        assert!(!expand_path("~/.config/lazydot.toml").exists());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_config_in_xdg_config_home() {
+        reset_test_environment();
+        let config_dir = get_home_dir().join("xdg");
+        unsafe {
+            env::set_var("XDG_CONFIG_HOME", &config_dir);
+        }
+        let mut config = Config::new();
+        config.add_path("~/.bashrc".to_string()).unwrap();
+        unsafe {
+            env::remove_var("XDG_CONFIG_HOME");
+        }
+
+        assert_eq!(config.config_file, config_dir.join("lazydot.toml"));
+        assert!(!expand_path("~/.config/lazydot.toml").exists());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_save_writes_to_loaded_file() {
+        reset_test_environment();
+        let config_file = get_home_dir().join("custom.toml");
+
+        let mut config = Config::load(&config_file);
+        config.dotfolder_path = "~/dotfiles".to_string();
+        config.save();
+
+        assert_eq!(Config::load(&config_file).dotfolder_path, "~/dotfiles");
+        assert_eq!(Config::new().dotfolder_path, "~/mydotfolder");
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_discovery_has_no_side_effects() {
+        reset_test_environment();
+        let local_config = expand_path("~/dotfiles/.config/lazydot.toml");
+        fs::create_dir_all(local_config.parent().unwrap()).unwrap();
+        fs::write(
+            &local_config,
+            "dotfolder_path = \"~/dotfiles\"\npaths = []\n[defaults]\n",
+        )
+        .unwrap();
+        env::set_current_dir(expand_path("~/dotfiles")).unwrap();
+
+        let config = Config::new();
+        assert_eq!(config.dotfolder_path, "~/dotfiles");
+        assert!(!Config::global_config_path().exists());
+        assert!(!Config::global_config_path().is_symlink());
+
+        env::set_current_dir(get_home_dir()).unwrap();
+        let config = Config::new();
+        assert_eq!(config.dotfolder_path, "~/mydotfolder");
+        assert!(!Config::global_config_path().exists());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_link_global_config() {
+        reset_test_environment();
+        let local_config = expand_path("~/dotfiles/.config/lazydot.toml");
+        fs::create_dir_all(local_config.parent().unwrap()).unwrap();
+        fs::write(
+            &local_config,
+            "dotfolder_path = \"~/dotfiles\"\npaths = []\n[defaults]\n",
+        )
+        .unwrap();
+
+        let global_config = Config::link_global(&local_config, false).unwrap();
+        assert!(global_config.is_symlink());
+        assert_eq!(Config::new().dotfolder_path, "~/dotfiles");
+
+        // Linking again is a no-op, another file needs --force
+        assert!(Config::link_global(&local_config, false).is_ok());
+        let other_config = expand_path("~/other.toml");
+        fs::copy(&local_config, &other_config).unwrap();
+        assert!(Config::link_global(&other_config, false).is_err());
+        assert!(Config::link_global(&other_config, true).is_ok());
+        assert_eq!(global_config.canonicalize().unwrap(), other_config);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_paths_with_variables() {
+        reset_test_environment();
+        unsafe {
+            env::set_var("LAZYDOT_TEST_CONF", get_home_dir().join(".config"));
+        }
+        let mut config = Config::new();
+        config
+            .add_path("$LAZYDOT_TEST_CONF/app1".to_string())
+            .unwrap();
+        config.add_path("~/.config/app1".to_string()).unwrap();
+        config
+            .add_path("${LAZYDOT_TEST_UNSET:-~/.config}/app2".to_string())
+            .unwrap();
+        assert!(
+            config
+                .add_path("$LAZYDOT_TEST_UNSET/app2".to_string())
+                .is_err()
+        );
+
+        let tracked = Config::new().tracked_paths();
+        assert!(tracked.contains(&"$LAZYDOT_TEST_CONF/app1".to_string()));
+        assert!(!tracked.contains(&"~/.config/app1".to_string()));
+        assert!(tracked.contains(&"${LAZYDOT_TEST_UNSET:-~/.config}/app2".to_string()));
+
+        config.remove_path("~/.config/app1".to_string()).unwrap();
+        assert!(!Config::new().is_tracked("~/.config/app1"));
+        let err = config
+            .remove_path("~/.nonexistent".to_string())
+            .unwrap_err();
+        assert!(err.contains("does not exist"), "{}", err);
+        unsafe {
+            env::remove_var("LAZYDOT_TEST_CONF");
+        }
+    }
+
+    #[test]
+    #[serial_test::serial]
+    #[should_panic(expected = "$LAZYDOT_TEST_UNSET is not defined")]
+    fn test_undefined_variable_in_config() {
+        reset_test_environment();
+        let config_file = get_home_dir().join("custom.toml");
+        let mut config = Config::load(&config_file);
+        config.dotfolder_path = "$LAZYDOT_TEST_UNSET/dotfiles".to_string();
+        config.save();
+    }
+
+    #[test]
+    #[serial_test::serial]
+    #[should_panic(expected = "mode = \"copy\" can't be combined with granularity = \"files\"")]
+    fn test_copy_mode_with_files_granularity() {
+        reset_test_environment();
+        let config_file = get_home_dir().join("custom.toml");
+        let mut config = Config::load(&config_file);
+        let mut entry = PathEntry::new("~/.config/fish".to_string());
+        entry.granularity = Some(Granularity::Files);
+        entry.ignore = Some(vec!["fish_variables".to_string()]);
+        config.paths.push(entry.clone());
+        config.save();
+        assert_eq!(Config::load(&config_file).paths.last(), Some(&entry));
+
+        entry.mode = Some(PathMode::Copy);
+        config.paths = vec![entry];
+        config.save();
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_condition_reasons() {
+        reset_test_environment();
+        unsafe {
+            env::set_var("LAZYDOT_TEST_SESSION", "wayland");
+        }
+        let condition = |when: &str| -> Condition { toml::from_str(when).unwrap() };
+        assert_eq!(
+            condition(&format!("os = \"{}\"", env::consts::OS)).unmet(),
+            None
+        );
+        assert_eq!(
+            condition("os = \"plan9\"").unmet(),
+            Some(format!("os is {}, not plan9", env::consts::OS))
+        );
+        assert_eq!(condition("env = \"LAZYDOT_TEST_SESSION\"").unmet(), None);
+        assert_eq!(
+            condition("env = \"LAZYDOT_TEST_SESSION=way*\"").unmet(),
+            None
+        );
+        assert_eq!(
+            condition("env = \"LAZYDOT_TEST_SESSION=x11\"").unmet(),
+            Some("$LAZYDOT_TEST_SESSION is wayland, not x11".to_string())
+        );
+        assert_eq!(
+            condition("env = \"LAZYDOT_TEST_UNSET\"").unmet(),
+            Some("$LAZYDOT_TEST_UNSET is not set".to_string())
+        );
+        assert_eq!(
+            condition("has_command = \"lazydot-no-such-command\"").unmet(),
+            Some("lazydot-no-such-command is not installed".to_string())
+        );
+        assert!(toml::from_str::<Condition>("hostnme = \"work\"").is_err());
+        unsafe {
+            env::remove_var("LAZYDOT_TEST_SESSION");
+        }
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_save_keeps_comments_and_entry_forms() {
+        reset_test_environment();
+        let config_file = get_home_dir().join("commented.toml");
+        fs::write(
+            &config_file,
+            r#"# My dotfiles
+dotfolder_path = "~/mydotfolder" # synced by git
+
+paths = [
+    # shell
+    "~/.bashrc",
+    # never overwrite this one
+    { path = "~/.ssh/config", on_duplicate = "skip", mode = "copy", note = "work keys" },
+    "~/.config/app1", # editor
+]
+
+[defaults]
+on_duplicate = "ask" # prompt
+"#,
+        )
+        .unwrap();
+
+        let mut config = Config::load(&config_file);
+        assert_eq!(config.paths[0], PathEntry::new("~/.bashrc".to_string()));
+        assert_eq!(config.paths[1].on_duplicate, Some(DuplicateBehavior::Skip));
+        assert_eq!(config.paths[1].mode, Some(PathMode::Copy));
+        assert_eq!(config.paths[1].note.as_deref(), Some("work keys"));
+        config.paths[2].mode = Some(PathMode::Copy);
+        config.add_path("~/.config/app2".to_string()).unwrap();
+
+        let content = fs::read_to_string(&config_file).unwrap();
+        for kept in [
+            "# My dotfiles",
+            "# synced by git",
+            "    # shell\n    \"~/.bashrc\",",
+            "    # never overwrite this one\n    { path = \"~/.ssh/config\", on_duplicate = \"skip\", mode = \"copy\", note = \"work keys\" },",
+            "{ path = \"~/.config/app1\", mode = \"copy\" }, # editor",
+            "\n    \"~/.config/app2\",\n]",
+            "on_duplicate = \"ask\" # prompt",
+        ] {
+            assert!(
+                content.contains(kept),
+                "Missing {:?} in:\n{}",
+                kept,
+                content
+            );
+        }
+        assert_eq!(Config::load(&config_file).paths, config.paths);
+        assert!(!content.contains("link_style"));
+
+        config.defaults.link_style = LinkStyle::Relative;
+        config.save();
+        let content = fs::read_to_string(&config_file).unwrap();
+        assert!(content.contains("link_style = \"relative\""));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_save_keeps_comments_of_removed_neighbours() {
+        reset_test_environment();
+        let config_file = get_home_dir().join("commented.toml");
+        fs::write(
+            &config_file,
+            r#"dotfolder_path = "~/mydotfolder"
+paths = [
+    # shell
+    "~/.bashrc", # rc
+    # z shell
+    "~/.zshrc", # z
+    "~/.profile", # last
+]
+
+[defaults]
+"#,
+        )
+        .unwrap();
+
+        // Each comment stays with its own entry, the removed ones go along
+        let mut config = Config::load(&config_file);
+        config.paths.remove(1);
+        config.save();
+        let content = fs::read_to_string(&config_file).unwrap();
+        assert!(
+            content.contains(
+                "paths = [\n    # shell\n    \"~/.bashrc\", # rc\n    \"~/.profile\", # last\n]"
+            ),
+            "{}",
+            content
+        );
+
+        let mut config = Config::load(&config_file);
+        config.paths.pop();
+        config.save();
+        let content = fs::read_to_string(&config_file).unwrap();
+        assert!(
+            content.contains("paths = [\n    # shell\n    \"~/.bashrc\", # rc\n]"),
+            "{}",
+            content
+        );
+        assert_eq!(Config::load(&config_file).paths, config.paths);
+    }
+}
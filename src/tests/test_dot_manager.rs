@@ -4,12 +4,16 @@
 #[allow(dead_code)]
 #[allow(unused_imports)]
 mod test {
-    use crate::config::{Config, DuplicateBehavior, OnDelinkBehavior};
-    use crate::dot_manager::DotManager;
+    use crate::config::{
+        Condition, Config, DuplicateBehavior, Granularity, LinkStyle, OnDelinkBehavior, PathEntry,
+        PathMode,
+    };
+    use crate::current_state::{CurrentState, FileKind, Outcome};
+    use crate::dot_manager::{DotManager, OrphanAction};
     use crate::utils::{
         copy_all, delete, expand_path, get_home_and_dot_path, get_home_dir_string,
-        get_path_in_dotfolder, init_config_with_paths, mock_dotfile_paths, reset_test_environment,
-        sync_config_with_manager,
+        get_path_in_dotfolder, get_state_dir, hash_path, init_config_with_paths, is_link_to,
+        mock_dotfile_paths, reset_test_environment, set_home_dir, sync_config_with_manager,
     };
     use std::path::PathBuf;
     use std::{env, fs};
@@ -49,7 +53,7 @@ mod test {
         }
 
         let home_path = get_home_dir_string();
-        for path in vec!["~/", "", &home_path] {
+        for path in ["~/", "", &home_path] {
             let err = config.add_path(path.to_string()).unwrap_err();
             assert!(
                 err.contains("home"),
@@ -80,7 +84,7 @@ mod test {
     fn test_sync_with_default_behavior() {
         reset_test_environment();
         let manager = sync_config_with_manager(DuplicateBehavior::Ask);
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             let (home, _) = get_home_and_dot_path(path);
             let dot =
                 get_path_in_dotfolder(&home).expect("failed to get path inside the dotfolder");
@@ -98,12 +102,12 @@ mod test {
         reset_test_environment();
         let manager = sync_config_with_manager(DuplicateBehavior::OverwriteHome);
 
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             assert_is_symlink(path);
         }
 
-        manager.delink(&manager.config.paths);
-        for path in &manager.config.paths {
+        manager.delink(&manager.config.tracked_paths());
+        for path in &manager.config.tracked_paths() {
             if path == "~/.config/lazydot.toml" {
                 continue;
             }
@@ -119,7 +123,7 @@ mod test {
 
         manager.sync();
         assert_correct_sync(&manager);
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             if path == "~/.config/lazydot.toml" {
                 continue;
             }
@@ -146,13 +150,13 @@ mod test {
         let mut manager = sync_config_with_manager(DuplicateBehavior::OverwriteDotfile);
         manager.config.defaults.on_delink = OnDelinkBehavior::Keep;
 
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             assert_is_symlink(path);
         }
 
-        manager.delink(&manager.config.paths);
+        manager.delink(&manager.config.tracked_paths());
 
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             if path == "~/.config/lazydot.toml" {
                 continue;
             }
@@ -167,7 +171,7 @@ mod test {
         manager.sync();
         assert_correct_sync(&manager);
 
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             if path == "~/.config/lazydot.toml" {
                 continue;
             }
@@ -191,13 +195,13 @@ mod test {
         reset_test_environment();
         let manager = sync_config_with_manager(DuplicateBehavior::Skip);
 
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             assert_is_symlink(path);
         }
 
-        manager.delink(&manager.config.paths);
+        manager.delink(&manager.config.tracked_paths());
 
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             if path == "~/.config/lazydot.toml" {
                 continue;
             }
@@ -211,7 +215,7 @@ mod test {
 
         manager.sync();
 
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             if path == "~/.config/lazydot.toml" {
                 continue;
             }
@@ -235,7 +239,7 @@ mod test {
             let (home, dot) = get_home_and_dot_path(path);
             assert!(home.is_symlink());
             assert!(home.canonicalize().unwrap().eq(&dot));
-            manager.delink(&[path.clone()].to_vec());
+            manager.delink(std::slice::from_ref(path));
             assert!(!home.is_symlink());
             assert!(!dot.exists());
         }
@@ -253,17 +257,34 @@ mod test {
             let (home, dot) = get_home_and_dot_path(path);
             assert!(home.is_symlink());
             assert!(home.canonicalize().unwrap().eq(&dot));
-            manager.delink(&[path.clone()].to_vec());
+            manager.delink(std::slice::from_ref(path));
             assert!(!home.is_symlink());
             assert!(dot.exists());
         }
     }
+    #[test]
+    #[serial_test::serial]
+    fn test_delink_absolute_path_keeps_own_on_delink() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        let mut config = Config::new();
+        config.paths[1].on_delink = Some(OnDelinkBehavior::Keep);
+        config.save();
+
+        // The shell hands over `~/.bashrc` expanded
+        let (home, dot) = get_home_and_dot_path(&config.paths[1].path);
+        DotManager::new().delink(&[home.display().to_string()]);
+        assert!(!home.is_symlink());
+        assert!(home.is_file());
+        assert!(dot.exists());
+    }
+
     #[test]
     #[serial_test::serial]
     fn test_resync_with_deleted_symlinks() {
         reset_test_environment();
         let manager = sync_config_with_manager(DuplicateBehavior::Ask);
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             if path == "~/.config/lazydot.toml" {
                 continue;
             }
@@ -274,7 +295,7 @@ mod test {
         }
         manager.delink_all();
 
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             if path == "~/.config/lazydot.toml" {
                 continue;
             }
@@ -285,7 +306,7 @@ mod test {
         manager.sync();
         assert_correct_sync(&manager);
 
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             if path == "~/.config/lazydot.toml" {
                 continue;
             }
@@ -303,7 +324,7 @@ mod test {
     fn test_resync_with_existing_broken_symlinks() {
         reset_test_environment();
         let mut manager = sync_config_with_manager(DuplicateBehavior::Ask);
-        let dotfolder_path = PathBuf::from(expand_path(&manager.config.dotfolder_path));
+        let dotfolder_path = expand_path(&manager.config.dotfolder_path);
         let secondary_dotfolder_path = dotfolder_path.join(expand_path("~/secondary"));
         copy_all(&dotfolder_path, &secondary_dotfolder_path).expect("failed to copy secondary");
         delete(&dotfolder_path);
@@ -325,7 +346,7 @@ mod test {
     fn test_resync_with_existing_symlinks() {
         reset_test_environment();
         let mut manager = sync_config_with_manager(DuplicateBehavior::OverwriteHome);
-        let dotfolder_path = PathBuf::from(expand_path(&manager.config.dotfolder_path));
+        let dotfolder_path = expand_path(&manager.config.dotfolder_path);
         let secondary_dotfolder_path = dotfolder_path.join(expand_path("~/secondary"));
         copy_all(&dotfolder_path, &secondary_dotfolder_path).expect("failed to copy secondary");
 
@@ -350,7 +371,7 @@ mod test {
             manager.sync();
             assert_correct_sync(&manager);
             manager.delink_all();
-            for path in &manager.config.paths {
+            for path in &manager.config.tracked_paths() {
                 let (home, dot) = get_home_and_dot_path(path);
                 assert!(home.exists() && !home.is_symlink());
                 assert!(dot.exists() && !dot.is_symlink());
@@ -359,7 +380,7 @@ mod test {
     }
 
     fn assert_correct_sync(manager: &DotManager) {
-        for path in &manager.config.paths {
+        for path in &manager.config.tracked_paths() {
             // duplicating the paths
             let (home, dot) = get_home_and_dot_path(path);
             assert!(
@@ -376,30 +397,885 @@ mod test {
         reset_test_environment();
         let _ = sync_config_with_manager(DuplicateBehavior::Ask);
         let mut manager = DotManager::new();
-        assert_eq!(manager.current_state.paths, manager.config.paths);
+        assert_eq!(
+            manager.current_state.linked_paths(),
+            manager.config.tracked_paths()
+        );
         let paths = mock_dotfile_paths();
-        for path in paths[0..2].to_vec() {
+        for path in paths[0..2].iter().cloned() {
             assert!(expand_path(&path).is_symlink());
             manager
                 .config
                 .add_path(path.clone())
                 .expect("TODO: panic message");
-            manager.config.remove_path(path);
+            manager.config.remove_path(path).unwrap();
         }
         let manager = DotManager::new();
         manager.sync();
         assert_correct_sync(&manager);
-        for path in paths[0..2].to_vec() {
-            let path = expand_path(&path);
+        for path in &paths[0..2] {
+            let path = expand_path(path);
             assert!(path.exists());
             assert!(!path.is_symlink());
         }
-        for path in paths[2..].to_vec() {
-            let (home, dot) = get_home_and_dot_path(&path);
+        for path in &paths[2..] {
+            let (home, dot) = get_home_and_dot_path(path);
             assert_eq!(home.canonicalize().expect("fail to canonicalize"), dot);
         }
     }
 
+    #[test]
+    #[serial_test::serial]
+    fn test_undo_first_sync() {
+        reset_test_environment();
+        let bashrc = read_file(&expand_path("~/.bashrc"));
+        let _ = sync_config_with_manager(DuplicateBehavior::Ask);
+        let manager = DotManager::new();
+        manager.undo();
+
+        for path in mock_dotfile_paths() {
+            let (home, dot) = get_home_and_dot_path(&path);
+            assert!(home.exists() && !home.is_symlink());
+            assert!(!dot.exists());
+        }
+        assert_eq!(read_file(&expand_path("~/.bashrc")), bashrc);
+        assert!(DotManager::new().current_state.paths.is_empty());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_undo_sync_with_overwrite_home() {
+        reset_test_environment();
+        let mut manager = sync_config_with_manager(DuplicateBehavior::OverwriteHome);
+        manager.config.defaults.on_delink = OnDelinkBehavior::Keep;
+        manager.delink_all();
+        let (home, dot) = get_home_and_dot_path("~/.bashrc");
+        fs::write(&home, "old home").unwrap();
+        fs::write(&dot, "old dotfile").unwrap();
+
+        DotManager::new().sync();
+        assert_eq!(read_file(&home), "old dotfile");
+
+        DotManager::new().undo();
+        assert!(!home.is_symlink());
+        assert_eq!(read_file(&home), "old home");
+        assert_eq!(read_file(&dot), "old dotfile");
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_sync_reports_failed_move() {
+        reset_test_environment();
+        init_config_with_paths();
+        // A file stands where the dotfolder needs a folder
+        fs::create_dir_all(expand_path("~/mydotfolder")).unwrap();
+        fs::write(expand_path("~/mydotfolder/.config"), "in the way").unwrap();
+
+        DotManager::new().sync();
+        let state = DotManager::new().current_state;
+        assert_eq!(
+            state.get("~/.config/app1").unwrap().outcome,
+            Outcome::Failed
+        );
+        assert!(expand_path("~/.config/app1").is_dir());
+        assert_eq!(state.get("~/.bashrc").unwrap().outcome, Outcome::Linked);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_overwrite_home_folder_with_dangling_link() {
+        reset_test_environment();
+        let mut manager = sync_config_with_manager(DuplicateBehavior::OverwriteHome);
+        manager.config.defaults.on_delink = OnDelinkBehavior::Keep;
+        manager.delink_all();
+        let (home, dot) = get_home_and_dot_path("~/.config/app1");
+        std::os::unix::fs::symlink("/nonexistent", home.join("broken")).unwrap();
+
+        DotManager::new().sync();
+        assert!(is_link_to(&home, &dot));
+
+        DotManager::new().undo();
+        assert!(home.is_dir() && !home.is_symlink());
+        assert_eq!(
+            fs::read_link(home.join("broken")).unwrap(),
+            PathBuf::from("/nonexistent")
+        );
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_undo_keeps_record_when_a_step_fails() {
+        reset_test_environment();
+        let mut manager = sync_config_with_manager(DuplicateBehavior::OverwriteHome);
+        manager.config.defaults.on_delink = OnDelinkBehavior::Keep;
+        manager.delink_all();
+        let (home, dot) = get_home_and_dot_path("~/.bashrc");
+        fs::write(&home, "old home").unwrap();
+        fs::write(&dot, "old dotfile").unwrap();
+        DotManager::new().sync();
+
+        // A folder took the place of the link, the overwritten file can't be put back yet
+        fs::remove_file(&home).unwrap();
+        fs::create_dir(&home).unwrap();
+        DotManager::new().undo();
+        assert!(home.is_dir());
+
+        // The backup is still there to retry once the way is clear
+        fs::remove_dir(&home).unwrap();
+        DotManager::new().undo();
+        assert_eq!(read_file(&home), "old home");
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_undo_interrupted_run() {
+        reset_test_environment();
+        let mut manager = sync_config_with_manager(DuplicateBehavior::OverwriteHome);
+        manager.config.defaults.on_delink = OnDelinkBehavior::Keep;
+        manager.delink_all();
+        let home = expand_path("~/.bashrc");
+        let content = read_file(&home);
+
+        // A run deletes a file and stops before finishing
+        let mut journal = DotManager::new().begin_journal("sync").unwrap();
+        journal.delete(&home).unwrap();
+        drop(journal);
+
+        // The next run doesn't start, the interrupted one is kept to be undone
+        DotManager::new().sync();
+        assert!(!home.exists());
+        DotManager::new().undo();
+        assert_eq!(read_file(&home), content);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_undo_disable_link() {
+        reset_test_environment();
+        let manager = sync_config_with_manager(DuplicateBehavior::Ask);
+        manager.delink_all();
+        for path in &manager.config.tracked_paths() {
+            assert_not_symlink(path);
+        }
+
+        manager.undo();
+        assert_correct_sync(&manager);
+
+        // A second undo has nothing left to revert
+        manager.undo();
+        assert_correct_sync(&manager);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_sync_with_relative_links() {
+        reset_test_environment();
+        let mut config = init_config_with_paths();
+        config.defaults.link_style = LinkStyle::Relative;
+        config.save();
+        let manager = DotManager::new();
+        manager.sync();
+        assert_correct_sync(&manager);
+        for path in &manager.config.paths {
+            let (home, _) = get_home_and_dot_path(&path.path);
+            assert!(fs::read_link(&home).unwrap().is_relative());
+        }
+
+        // Switching back re-creates the links as absolute ones
+        let mut config = Config::new();
+        config.defaults.link_style = LinkStyle::Absolute;
+        config.paths[1].link_style = Some(LinkStyle::Relative);
+        config.save();
+        let manager = DotManager::new();
+        manager.sync();
+        assert_correct_sync(&manager);
+        for (i, path) in manager.config.paths.iter().enumerate() {
+            let (home, _) = get_home_and_dot_path(&path.path);
+            assert_eq!(fs::read_link(&home).unwrap().is_relative(), i == 1);
+        }
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_path_entry_round_trip() {
+        reset_test_environment();
+        let mut config = init_config_with_paths();
+        config.paths[1].link_style = Some(LinkStyle::Relative);
+        config.save();
+
+        let config = Config::new();
+        assert_eq!(
+            config.paths[0],
+            PathEntry::new("~/.config/lazydot.toml".to_string())
+        );
+        assert_eq!(config.paths[1].path, "~/.bashrc");
+        assert_eq!(config.link_style_for(&config.paths[1]), LinkStyle::Relative);
+        assert_eq!(config.link_style_for(&config.paths[2]), LinkStyle::Absolute);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_find_untracked() {
+        reset_test_environment();
+        let _ = sync_config_with_manager(DuplicateBehavior::Ask);
+        let dotfolder = expand_path("~/mydotfolder");
+        for path in [
+            ".config/app2/other.toml",
+            "notes.txt",
+            "README.md",
+            ".git/HEAD",
+        ] {
+            let path = dotfolder.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "untracked").unwrap();
+        }
+        fs::write(dotfolder.join(".lazydotignore"), "# docs\n*.md\n").unwrap();
+
+        let manager = DotManager::new();
+        assert_eq!(
+            manager.find_untracked(),
+            vec![
+                dotfolder.join(".config/app2/other.toml"),
+                dotfolder.join("notes.txt")
+            ]
+        );
+
+        // Dotfolder paths are added as their place in home
+        let mut config = Config::new();
+        config
+            .add_path(dotfolder.join("notes.txt").to_string_lossy().to_string())
+            .unwrap();
+        assert!(config.tracked_paths().contains(&"~/notes.txt".to_string()));
+        assert_eq!(
+            DotManager::new().find_untracked(),
+            vec![dotfolder.join(".config/app2/other.toml")]
+        );
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_state_kept_outside_dotfolder() {
+        reset_test_environment();
+        let manager = sync_config_with_manager(DuplicateBehavior::Ask);
+        let state_file = CurrentState::dir(&manager.config).join("current_state.toml");
+        assert!(state_file.starts_with(get_state_dir()));
+        assert!(state_file.exists());
+
+        // Kept per machine, under the name `hostname` gives it
+        if let Ok(output) = std::process::Command::new("hostname").output() {
+            let hostname = String::from_utf8(output.stdout).unwrap();
+            assert!(state_file.starts_with(get_state_dir().join(hostname.trim())));
+        }
+        assert!(!expand_path("~/mydotfolder/current_state.toml").exists());
+
+        // A state file left in the dotfolder by older versions is read as is, and only moved
+        // out by a command writing the state
+        let legacy_file = expand_path("~/mydotfolder/current_state.toml");
+        fs::rename(&state_file, &legacy_file).unwrap();
+        let manager = DotManager::new();
+        assert_eq!(
+            manager.current_state.linked_paths(),
+            manager.config.tracked_paths()
+        );
+        manager.status();
+        manager.check();
+        assert!(legacy_file.exists());
+        assert!(!state_file.exists());
+
+        manager.sync();
+        assert!(state_file.exists());
+        assert!(!legacy_file.exists());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_state_records_sync_outcome() {
+        reset_test_environment();
+        let mut manager = sync_config_with_manager(DuplicateBehavior::Skip);
+        manager.config.defaults.on_delink = OnDelinkBehavior::Keep;
+        manager.delink(&["~/.bashrc".to_string()]);
+        let mut config = Config::new();
+        config.paths.push(PathEntry::new("~/.missing".to_string()));
+        config.save();
+        DotManager::new().sync();
+
+        let state = DotManager::new().current_state;
+        let bashrc = state.get("~/.bashrc").unwrap();
+        assert_eq!(bashrc.outcome, Outcome::Skipped);
+        assert_eq!(bashrc.target, None);
+        assert_eq!(state.get("~/.missing").unwrap().outcome, Outcome::Missing);
+
+        let (home, dot) = get_home_and_dot_path("~/.config/app1");
+        let app1 = state.get("~/.config/app1").unwrap();
+        assert_eq!(app1.outcome, Outcome::Linked);
+        assert_eq!(app1.target, Some(dot.clone()));
+        assert_eq!(app1.kind, Some(FileKind::Dir));
+        assert_eq!(app1.hash, hash_path(&home));
+        assert!(!state.linked_paths().contains(&"~/.bashrc".to_string()));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_legacy_state_format() {
+        let state: CurrentState = toml::from_str("paths = [\"~/.bashrc\"]").unwrap();
+        assert_eq!(state.linked_paths(), vec!["~/.bashrc"]);
+        assert_eq!(state.get("~/.bashrc").unwrap().hash, None);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_rebuild_state() {
+        reset_test_environment();
+        let manager = sync_config_with_manager(DuplicateBehavior::Ask);
+        let state_file = CurrentState::dir(&manager.config).join("current_state.toml");
+
+        // Lose the state, then stop tracking a linked path
+        delete(&state_file);
+        let mut config = Config::new();
+        config.remove_path("~/.bashrc".to_string()).unwrap();
+        let mut manager = DotManager::without_state();
+        manager.rebuild_state(0);
+        assert!(state_file.exists());
+        let mut linked = manager.current_state.linked_paths();
+        linked.sort();
+        assert_eq!(
+            linked,
+            vec![
+                "~/.bashrc",
+                "~/.config/app1",
+                "~/.config/app2/app_config2.toml",
+                "~/.config/lazydot.toml"
+            ]
+        );
+        let (home, dot) = get_home_and_dot_path("~/.config/app1");
+        assert_eq!(
+            manager.current_state.get("~/.config/app1").unwrap().target,
+            Some(dot)
+        );
+        assert!(home.is_symlink());
+
+        // The untracked link is cleaned up again by the next sync
+        DotManager::new().sync();
+        assert_not_symlink("~/.bashrc");
+        assert!(expand_path("~/.bashrc").exists());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_damaged_state_is_reported() {
+        reset_test_environment();
+        let manager = sync_config_with_manager(DuplicateBehavior::Ask);
+        let state_file = CurrentState::dir(&manager.config).join("current_state.toml");
+        fs::write(&state_file, "paths = [").unwrap();
+
+        let err = CurrentState::new(&manager.config).unwrap_err();
+        assert!(err.contains("current_state.toml"), "Error: {}", err);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_find_orphan_links() {
+        reset_test_environment();
+        let manager = sync_config_with_manager(DuplicateBehavior::Ask);
+        let dotfolder = expand_path("~/mydotfolder");
+        fs::write(dotfolder.join("orphan.txt"), "orphan").unwrap();
+        std::os::unix::fs::symlink(dotfolder.join("orphan.txt"), expand_path("~/.orphan")).unwrap();
+        std::os::unix::fs::symlink("mydotfolder/gone", expand_path("~/.dangling")).unwrap();
+        std::os::unix::fs::symlink("/tmp", expand_path("~/.elsewhere")).unwrap();
+
+        assert_eq!(
+            manager.find_orphan_links(0),
+            vec![expand_path("~/.dangling"), expand_path("~/.orphan")]
+        );
+
+        // Nothing is touched without a terminal to ask in
+        manager.gc(0);
+        assert_is_symlink("~/.orphan");
+        assert_is_symlink("~/.dangling");
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gc_actions() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        let dotfolder = expand_path("~/mydotfolder");
+        fs::write(dotfolder.join("orphan.txt"), "orphan").unwrap();
+        let (orphan, dangling) = (expand_path("~/.orphan"), expand_path("~/.dangling"));
+        std::os::unix::fs::symlink(dotfolder.join("orphan.txt"), &orphan).unwrap();
+        std::os::unix::fs::symlink("mydotfolder/gone", &dangling).unwrap();
+
+        // A dangling link can only be removed or left
+        let offered = std::cell::RefCell::new(Vec::new());
+        DotManager::new().gc_with(0, |link, actions| {
+            offered
+                .borrow_mut()
+                .push((link.to_path_buf(), actions.to_vec()));
+            OrphanAction::Leave
+        });
+        assert_eq!(
+            offered.into_inner(),
+            vec![
+                (
+                    dangling.clone(),
+                    vec![OrphanAction::Remove, OrphanAction::Leave]
+                ),
+                (
+                    orphan.clone(),
+                    vec![
+                        OrphanAction::Delink,
+                        OrphanAction::Remove,
+                        OrphanAction::Leave
+                    ]
+                ),
+            ]
+        );
+        assert!(dangling.is_symlink() && orphan.is_symlink());
+
+        // Delinking copies, the dotfolder keeps its file
+        DotManager::new().gc_with(0, |link, _| match link == orphan {
+            true => OrphanAction::Delink,
+            false => OrphanAction::Leave,
+        });
+        assert!(!orphan.is_symlink());
+        assert_eq!(read_file(&orphan), "orphan");
+        assert!(dotfolder.join("orphan.txt").exists());
+        assert!(dangling.is_symlink());
+
+        DotManager::new().gc_with(0, |_, _| OrphanAction::Remove);
+        assert!(!dangling.exists() && !dangling.is_symlink());
+
+        // Each choice can be undone
+        DotManager::new().undo();
+        assert!(dangling.is_symlink());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_dotfolder_outside_home() {
+        reset_test_environment();
+        let dotfolder = tempfile::tempdir().unwrap().keep();
+        let mut config = Config::new();
+        config.dotfolder_path = dotfolder.display().to_string();
+        config.defaults.link_style = LinkStyle::Relative;
+        config.save();
+        fs::write(dotfolder.join(".vimrc"), "set number").unwrap();
+
+        // Paths given inside the dotfolder refer to their place in home
+        let mut config = init_config_with_paths();
+        config
+            .add_path(dotfolder.join(".vimrc").display().to_string())
+            .unwrap();
+        assert!(config.tracked_paths().contains(&"~/.vimrc".to_string()));
+
+        let manager = DotManager::new();
+        manager.sync();
+        assert_correct_sync(&manager);
+        assert!(dotfolder.join(".bashrc").is_file());
+        assert_eq!(
+            fs::canonicalize(expand_path("~/.vimrc")).unwrap(),
+            dotfolder.join(".vimrc").canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_deploy_into_other_home() {
+        reset_test_environment();
+        let manager = sync_config_with_manager(DuplicateBehavior::Ask);
+        let own_state = CurrentState::dir(&manager.config);
+        let target = tempfile::tempdir().unwrap().keep();
+
+        set_home_dir(Some(target.clone()));
+        let manager = DotManager::new();
+        assert!(manager.current_state.paths.is_empty());
+        manager.sync();
+        let target_state = CurrentState::dir(&manager.config);
+        let config_file = manager.config.config_file.clone();
+        set_home_dir(None);
+
+        // The links point at the dotfolder of the current user, nothing is copied into the target
+        assert!(is_link_to(
+            &target.join(".bashrc"),
+            &expand_path("~/mydotfolder/.bashrc")
+        ));
+        assert!(is_link_to(
+            &target.join(".config/app1"),
+            &expand_path("~/mydotfolder/.config/app1")
+        ));
+        assert!(!target.join("mydotfolder").exists());
+        // The config and state of the current user are used, the state is kept per target
+        assert_eq!(config_file, expand_path("~/.config/lazydot.toml"));
+        assert_ne!(own_state, target_state);
+        assert!(target_state.join("current_state.toml").exists());
+        assert_correct_sync(&DotManager::new());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_conditional_paths() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        assert_is_symlink("~/.bashrc");
+
+        let mut config = Config::new();
+        config.paths[1].when = Some(Condition {
+            hostname: Some("no-such-host-*".to_string()),
+            ..Condition::default()
+        });
+        config.paths[2].when = Some(Condition {
+            os: Some(env::consts::OS.to_string()),
+            ..Condition::default()
+        });
+        config.save();
+
+        let config = Config::new();
+        assert_eq!(
+            config.paths[1].when.as_ref().unwrap().hostname.as_deref(),
+            Some("no-such-host-*")
+        );
+        let excluded = config.excluded_entries();
+        assert_eq!(excluded.len(), 1);
+        assert_eq!(excluded[0].0.path, "~/.bashrc");
+        assert!(excluded[0].1.contains("doesn't match no-such-host-*"));
+        assert!(!config.tracked_paths().contains(&"~/.bashrc".to_string()));
+
+        // A path excluded on this machine is unlinked and not linked again
+        let manager = DotManager::new();
+        manager.sync();
+        assert_not_symlink("~/.bashrc");
+        assert_is_symlink(&manager.config.paths[2].path);
+        manager.sync();
+        assert_not_symlink("~/.bashrc");
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_excluded_path_keeps_dotfolder_copy() {
+        reset_test_environment();
+        let manager = sync_config_with_manager(DuplicateBehavior::Ask);
+        assert!(matches!(
+            manager.config.defaults.on_delink,
+            OnDelinkBehavior::Remove
+        ));
+        let (home, dot) = get_home_and_dot_path("~/.bashrc");
+        let content = read_file(&dot);
+
+        // Syncing where the condition no longer holds unlinks it, as `keep` would
+        let mut config = Config::new();
+        config.paths[1].when = Some(Condition {
+            env: Some("LAZYDOT_TEST_UNSET".to_string()),
+            ..Condition::default()
+        });
+        config.save();
+        DotManager::new().sync();
+        assert_not_symlink("~/.bashrc");
+        assert_eq!(read_file(&home), content);
+        assert_eq!(read_file(&dot), content);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_copy_mode() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        let mut config = Config::new();
+        config.paths[1].mode = Some(PathMode::Copy);
+        config.save();
+
+        // A linked path is replaced by a copy, then left alone while both sides match
+        let manager = DotManager::new();
+        manager.sync();
+        let (home, dot) = get_home_and_dot_path("~/.bashrc");
+        assert_not_symlink("~/.bashrc");
+        assert_eq!(read_file(&home), read_file(&dot));
+        DotManager::new().sync();
+        assert_not_symlink("~/.bashrc");
+
+        // An edited copy is a duplicate, settled by the per-path on_duplicate
+        fs::write(&home, "edited in home").unwrap();
+        let mut config = Config::new();
+        config.paths[1].on_duplicate = Some(DuplicateBehavior::OverwriteDotfile);
+        config.save();
+        DotManager::new().sync();
+        assert_eq!(read_file(&dot), "edited in home");
+        assert_eq!(read_file(&home), "edited in home");
+
+        // Untracking a copy leaves the file in home
+        let mut config = Config::new();
+        config.remove_path("~/.bashrc".to_string()).unwrap();
+        DotManager::new().sync();
+        assert_eq!(read_file(&home), "edited in home");
+        assert!(!dot.exists());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_delink_copy_given_expanded() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        let mut config = Config::new();
+        config.paths[1].mode = Some(PathMode::Copy);
+        config.save();
+        DotManager::new().sync();
+
+        // The copy stays in home and the unchanged dotfolder copy goes, as for `~/.bashrc`
+        let (home, dot) = get_home_and_dot_path("~/.bashrc");
+        DotManager::new().delink(&[home.display().to_string()]);
+        assert!(home.is_file() && !home.is_symlink());
+        assert!(!dot.exists());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_per_path_policies() {
+        reset_test_environment();
+        let manager = sync_config_with_manager(DuplicateBehavior::OverwriteHome);
+        manager.delink(&["~/.bashrc".to_string()]);
+        let (bashrc, bashrc_dot) = get_home_and_dot_path("~/.bashrc");
+        fs::write(&bashrc, "home bashrc").unwrap();
+        fs::write(&bashrc_dot, "dotfolder bashrc").unwrap();
+
+        // Never overwrite this one in home
+        let mut config = Config::new();
+        config.paths[1].on_duplicate = Some(DuplicateBehavior::Skip);
+        config.paths[2].on_delink = Some(OnDelinkBehavior::Keep);
+        config.save();
+        let manager = DotManager::new();
+        manager.sync();
+        assert_not_symlink("~/.bashrc");
+        assert_eq!(read_file(&bashrc), "home bashrc");
+
+        // The own on_delink still applies once the path is removed from the config
+        let kept = manager.config.paths[2].path.clone();
+        let (_, kept_dot) = get_home_and_dot_path(&kept);
+        let mut config = Config::new();
+        config.remove_path(kept.clone()).unwrap();
+        DotManager::new().sync();
+        assert_not_symlink(&kept);
+        assert!(kept_dot.exists());
+
+        let removed = manager.config.paths[3].path.clone();
+        let (_, removed_dot) = get_home_and_dot_path(&removed);
+        let mut config = Config::new();
+        config.remove_path(removed.clone()).unwrap();
+        DotManager::new().sync();
+        assert_not_symlink(&removed);
+        assert!(!removed_dot.exists());
+    }
+
+    /// Unlinks `path` and leaves different content in home and in the dotfolder.
+    fn make_duplicate(manager: &DotManager, path: &str, home_content: &str, dot_content: &str) {
+        manager.delink(&[path.to_string()]);
+        let (home, dot) = get_home_and_dot_path(path);
+        fs::write(&home, home_content).unwrap();
+        fs::write(&dot, dot_content).unwrap();
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_identical_duplicates_are_linked() {
+        reset_test_environment();
+        let mut manager = sync_config_with_manager(DuplicateBehavior::Ask);
+        manager.config.defaults.on_delink = OnDelinkBehavior::Keep;
+        manager.delink(&["~/.bashrc".to_string()]);
+
+        // Asking would fail without a terminal
+        for behavior in [DuplicateBehavior::Ask, DuplicateBehavior::IdenticalOnly] {
+            let mut config = Config::new();
+            config.defaults.on_duplicate = behavior;
+            config.save();
+            DotManager::new().sync();
+            assert_is_symlink("~/.bashrc");
+            let (home, _) = get_home_and_dot_path("~/.bashrc");
+            assert!(!home.with_file_name(".bashrc.bak").exists());
+            assert!(!CurrentState::dir(&manager.config).join("backups").exists());
+            manager.delink(&["~/.bashrc".to_string()]);
+        }
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_sync_with_backup_dotfile() {
+        reset_test_environment();
+        let manager = sync_config_with_manager(DuplicateBehavior::BackupDotfile);
+        make_duplicate(&manager, "~/.bashrc", "home", "dotfolder");
+
+        DotManager::new().sync();
+        let (home, dot) = get_home_and_dot_path("~/.bashrc");
+        assert!(is_link_to(&home, &dot));
+        assert_eq!(read_file(&dot), "home");
+        // Backups stay out of the dotfolder and never replace an older one
+        let backups = CurrentState::dir(&manager.config).join("backups/dotfolder");
+        assert_eq!(read_file(&backups.join(".bashrc.bak")), "dotfolder");
+        assert!(!dot.with_file_name(".bashrc.bak").exists());
+
+        make_duplicate(&manager, "~/.bashrc", "home again", "dotfolder again");
+        DotManager::new().sync();
+        assert_eq!(read_file(&dot), "home again");
+        assert_eq!(read_file(&backups.join(".bashrc.bak")), "dotfolder");
+        assert_eq!(read_file(&backups.join(".bashrc.bak.1")), "dotfolder again");
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_sync_with_newest() {
+        reset_test_environment();
+        let manager = sync_config_with_manager(DuplicateBehavior::Newest);
+        let paths = ["~/.bashrc", "~/.config/app2/app_config2.toml"];
+        make_duplicate(&manager, paths[0], "newer home", "older dotfolder");
+        make_duplicate(&manager, paths[1], "older home", "newer dotfolder");
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        let (_, dot) = get_home_and_dot_path(paths[0]);
+        fs::File::options()
+            .write(true)
+            .open(&dot)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        let (home, _) = get_home_and_dot_path(paths[1]);
+        fs::File::options()
+            .write(true)
+            .open(&home)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        DotManager::new().sync();
+        for (path, kept) in [(paths[0], "newer home"), (paths[1], "newer dotfolder")] {
+            let (home, dot) = get_home_and_dot_path(path);
+            assert!(is_link_to(&home, &dot));
+            assert_eq!(read_file(&dot), kept);
+        }
+        // The dotfolder copy is backed up in the state, the home copy next to itself
+        let backups = CurrentState::dir(&manager.config).join("backups/dotfolder");
+        assert_eq!(read_file(&backups.join(".bashrc.bak")), "older dotfolder");
+        let (home, _) = get_home_and_dot_path(paths[1]);
+        assert_eq!(
+            read_file(&home.with_file_name("app_config2.toml.bak")),
+            "older home"
+        );
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_sync_with_merge() {
+        reset_test_environment();
+        let mut manager = sync_config_with_manager(DuplicateBehavior::Merge);
+        manager.config.defaults.on_delink = OnDelinkBehavior::Keep;
+        manager.delink(&[".config/app1".to_string()]);
+        let (home, dot) = get_home_and_dot_path("~/.config/app1");
+        for (path, content) in [
+            (home.join("home_only.toml"), "home"),
+            (home.join("nested/home_only.toml"), "nested home"),
+            (dot.join("dot_only.toml"), "dot"),
+            (home.join("differs.toml"), "home version"),
+            (dot.join("differs.toml"), "dotfolder version"),
+        ] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        // A differing file is asked about; without a terminal the folder is left as it is
+        DotManager::new().sync();
+        assert!(!home.is_symlink());
+        assert_eq!(read_file(&home.join("home_only.toml")), "home");
+        assert_eq!(read_file(&home.join("differs.toml")), "home version");
+        assert_eq!(read_file(&dot.join("differs.toml")), "dotfolder version");
+        assert!(!dot.join("home_only.toml").exists());
+        assert!(!dot.join("differs.toml.bak").exists());
+
+        // Once nothing differs, both copies are combined
+        fs::write(home.join("differs.toml"), "dotfolder version").unwrap();
+        DotManager::new().sync();
+        assert!(is_link_to(&home, &dot));
+        assert_eq!(read_file(&dot.join("home_only.toml")), "home");
+        assert_eq!(read_file(&dot.join("nested/home_only.toml")), "nested home");
+        assert_eq!(read_file(&dot.join("dot_only.toml")), "dot");
+        assert!(dot.join("app_config1.toml").is_file());
+        assert_eq!(read_file(&dot.join("differs.toml")), "dotfolder version");
+        assert!(!dot.join("differs.toml.bak").exists());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_files_granularity() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::OverwriteHome);
+        let (home, dot) = get_home_and_dot_path("~/.config/app1");
+        fs::write(dot.join("cache.log"), "ignored").unwrap();
+        let mut config = Config::new();
+        let entry = config
+            .paths
+            .iter_mut()
+            .find(|entry| expand_path(&entry.path) == home)
+            .unwrap();
+        let path = entry.path.clone();
+        entry.granularity = Some(Granularity::Files);
+        entry.ignore = Some(vec!["*.log".to_string()]);
+        config.save();
+
+        // The link to the whole folder becomes a real folder holding a link per file
+        DotManager::new().sync();
+        assert!(home.is_dir() && !home.is_symlink());
+        assert!(is_link_to(
+            &home.join("app_config1.toml"),
+            &dot.join("app_config1.toml")
+        ));
+        assert!(!home.join("cache.log").exists());
+        assert!(DotManager::new().find_orphan_links(2).is_empty());
+
+        // Files written by the program are moved into the dotfolder unless ignored
+        fs::write(home.join("state.log"), "runtime").unwrap();
+        fs::write(home.join("new.toml"), "new").unwrap();
+        DotManager::new().sync();
+        assert_eq!(read_file(&home.join("state.log")), "runtime");
+        assert!(!dot.join("state.log").exists());
+        assert!(is_link_to(&home.join("new.toml"), &dot.join("new.toml")));
+        assert_eq!(read_file(&dot.join("new.toml")), "new");
+
+        // Delinking puts the files back and leaves the program's own alone, the path can be
+        // given expanded by the shell
+        assert!(path.starts_with('~'));
+        DotManager::new().delink(&[home.display().to_string()]);
+        assert!(!home.join("app_config1.toml").is_symlink());
+        assert!(home.join("app_config1.toml").is_file());
+        assert_eq!(read_file(&home.join("new.toml")), "new");
+        assert_eq!(read_file(&home.join("state.log")), "runtime");
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_files_granularity_backups() {
+        reset_test_environment();
+        let manager = sync_config_with_manager(DuplicateBehavior::BackupHome);
+        let (home, dot) = get_home_and_dot_path("~/.config/app1");
+        let mut config = Config::new();
+        let entry = config
+            .paths
+            .iter_mut()
+            .find(|entry| expand_path(&entry.path) == home)
+            .unwrap();
+        entry.granularity = Some(Granularity::Files);
+        config.save();
+        DotManager::new().sync();
+
+        // A conflicting file is backed up out of the folder, not picked up as a new file
+        let file = home.join("app_config1.toml");
+        fs::remove_file(&file).unwrap();
+        fs::write(&file, "edited in home").unwrap();
+        DotManager::new().sync();
+        DotManager::new().sync();
+        assert!(is_link_to(&file, &dot.join("app_config1.toml")));
+        let backups = CurrentState::dir(&manager.config).join("backups/home/.config/app1");
+        assert_eq!(
+            read_file(&backups.join("app_config1.toml.bak")),
+            "edited in home"
+        );
+        assert!(!home.join("app_config1.toml.bak").exists());
+        assert!(!dot.join("app_config1.toml.bak").exists());
+    }
+
     #[test]
     #[serial_test::serial]
     fn test_delink() {}
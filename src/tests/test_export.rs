@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod test {
+    use crate::args::ConfigFormat;
+    use crate::config::{Condition, Config, DuplicateBehavior};
+    use crate::export::{export, export_config};
+    use crate::utils::{expand_path, is_link_to, reset_test_environment, sync_config_with_manager};
+    use flate2::read::GzDecoder;
+    use std::fs;
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    #[test]
+    #[serial_test::serial]
+    fn test_export_to_folder() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        let out = expand_path("~/export");
+
+        assert_eq!(export(&Config::new(), &out).unwrap(), 4);
+        assert!(out.join(".bashrc").is_file());
+        assert!(!out.join(".bashrc").is_symlink());
+        assert!(out.join(".config/app1").is_dir());
+        assert!(out.join(".config/app2/app_config2.toml").is_file());
+        assert!(expand_path("~/.bashrc").is_symlink());
+
+        // An existing folder is never written into
+        assert!(export(&Config::new(), &out).is_err());
+
+        // Paths excluded on this machine are left out
+        let mut config = Config::new();
+        config.paths[1].when = Some(Condition {
+            hostname: Some("no-such-host-*".to_string()),
+            ..Condition::default()
+        });
+        let out = expand_path("~/export-active");
+        assert_eq!(export(&config, &out).unwrap(), 3);
+        assert!(!out.join(".bashrc").exists());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_export_to_tarball() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        let out = expand_path("~/out/dotfiles.tar.gz");
+
+        export(&Config::new(), &out).unwrap();
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(&out).unwrap()));
+        let entries: Vec<PathBuf> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect();
+        assert!(entries.contains(&PathBuf::from(".bashrc")));
+        assert!(entries.contains(&PathBuf::from(".config/app2/app_config2.toml")));
+        assert!(fs::metadata(&out).unwrap().len() > 0);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_export_config_stow() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        let out = expand_path("~/stow/lazydot");
+
+        let count = export_config(&Config::new(), ConfigFormat::Stow, &out).unwrap();
+        assert_eq!(count, 4);
+        let dotfolder = expand_path("~/mydotfolder");
+        assert!(is_link_to(&out.join(".bashrc"), &dotfolder.join(".bashrc")));
+        assert!(is_link_to(
+            &out.join(".config/app1"),
+            &dotfolder.join(".config/app1")
+        ));
+        assert!(fs::read_link(out.join(".bashrc")).unwrap().is_relative());
+        assert!(export_config(&Config::new(), ConfigFormat::Stow, &out).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_export_config_home_manager() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        let out = expand_path("~/home.nix");
+
+        export_config(&Config::new(), ConfigFormat::HomeManager, &out).unwrap();
+        let module = fs::read_to_string(&out).unwrap();
+        assert!(module.contains(
+            "\".bashrc\".source = config.lib.file.mkOutOfStoreSymlink \"${config.home.homeDirectory}/mydotfolder/.bashrc\";"
+        ));
+        assert!(module.contains("\".config/app1\".source"));
+        assert!(export_config(&Config::new(), ConfigFormat::HomeManager, &out).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_export_config_chezmoi() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        let out = expand_path("~/chezmoi");
+
+        export_config(&Config::new(), ConfigFormat::Chezmoi, &out).unwrap();
+        assert_eq!(
+            fs::read_to_string(out.join("symlink_dot_bashrc.tmpl")).unwrap(),
+            "{{ .chezmoi.homeDir }}/mydotfolder/.bashrc\n"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("dot_config/symlink_app1.tmpl")).unwrap(),
+            "{{ .chezmoi.homeDir }}/mydotfolder/.config/app1\n"
+        );
+    }
+}
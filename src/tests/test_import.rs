@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::dot_manager::DotManager;
+    use crate::import::{StowStatus, import_stow, plan_stow};
+    use crate::utils::{expand_path, is_link_to, reset_test_environment};
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    /// A stow directory at ~/stow with a stowed file, a stowed folder, a package that was
+    /// never stowed and one clashing with the real ~/.bashrc.
+    fn create_stow_dir() {
+        for (path, content) in [
+            ("vim/.vimrc", "set number"),
+            ("vim/README.md", "docs"),
+            ("nvim/.config/nvim/init.lua", "-- init"),
+            ("nvim/.config/nvim/.netrwhist", "history"),
+            (
+                "nvim/.stow-local-ignore",
+                "# editor history\n\\.netrwhist\n",
+            ),
+            ("zsh/.zshrc", "zsh"),
+            ("bash/.bashrc", "stow bashrc"),
+        ] {
+            let path = expand_path("~/stow").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        symlink("stow/vim/.vimrc", expand_path("~/.vimrc")).unwrap();
+        symlink("../stow/nvim/.config/nvim", expand_path("~/.config/nvim")).unwrap();
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_plan_stow() {
+        reset_test_environment();
+        create_stow_dir();
+
+        let plan: Vec<(String, StowStatus)> = plan_stow(&expand_path("~/stow"), &[])
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.path(), entry.status))
+            .collect();
+        assert_eq!(
+            plan,
+            vec![
+                (
+                    "~/.bashrc".to_string(),
+                    StowStatus::Conflict("exists in home".to_string())
+                ),
+                ("~/.config/nvim".to_string(), StowStatus::Stowed),
+                ("~/.vimrc".to_string(), StowStatus::Stowed),
+                ("~/.zshrc".to_string(), StowStatus::Unstowed),
+            ]
+        );
+        assert!(plan_stow(&expand_path("~/stow"), &["emacs".to_string()]).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_import_stow() {
+        reset_test_environment();
+        create_stow_dir();
+
+        // A dry run changes nothing
+        import_stow(&mut DotManager::new(), "~/stow", &[], true).unwrap();
+        assert!(
+            Config::new()
+                .paths
+                .iter()
+                .all(|entry| entry.path != "~/.vimrc")
+        );
+        assert!(expand_path("~/stow/vim/.vimrc").exists());
+
+        let packages = ["vim".to_string(), "nvim".to_string(), "zsh".to_string()];
+        import_stow(&mut DotManager::new(), "~/stow", &packages, false).unwrap();
+        let dotfolder = expand_path("~/mydotfolder");
+        assert!(is_link_to(
+            &expand_path("~/.vimrc"),
+            &dotfolder.join(".vimrc")
+        ));
+        assert!(is_link_to(
+            &expand_path("~/.config/nvim"),
+            &dotfolder.join(".config/nvim")
+        ));
+        assert!(dotfolder.join(".config/nvim/.netrwhist").exists());
+        assert!(dotfolder.join(".zshrc").is_file());
+        assert!(!expand_path("~/.zshrc").exists());
+
+        // Ignored files stay in the package
+        assert!(expand_path("~/stow/vim/README.md").exists());
+        assert!(!expand_path("~/stow/zsh").exists());
+        let tracked = Config::new().tracked_paths();
+        for path in ["~/.vimrc", "~/.config/nvim", "~/.zshrc"] {
+            assert!(tracked.contains(&path.to_string()), "Not tracked: {}", path);
+        }
+
+        DotManager::new().sync();
+        assert!(is_link_to(
+            &expand_path("~/.zshrc"),
+            &dotfolder.join(".zshrc")
+        ));
+
+        // The clashing package is left alone
+        assert!(!expand_path("~/.bashrc").is_symlink());
+        assert!(expand_path("~/stow/bash/.bashrc").exists());
+    }
+}
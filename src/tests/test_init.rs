@@ -0,0 +1,114 @@
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::dot_manager::{DotManager, PlannedAction};
+    use crate::init::{bootstrap, find_common_dotfiles, init, link_repo_config};
+    use crate::utils::{expand_path, get_home_dir, is_link_to, reset_test_environment};
+    use std::fs;
+
+    /// A cloned dotfolder at ~/repo tracking its own config, ~/.vimrc and ~/.bashrc.
+    fn create_repo(on_duplicate: &str) {
+        let repo_config = expand_path("~/repo/.config/lazydot.toml");
+        fs::create_dir_all(repo_config.parent().unwrap()).unwrap();
+        fs::write(
+            &repo_config,
+            format!(
+                "dotfolder_path = \"~/repo\"\n\
+                 paths = [\"~/.config/lazydot.toml\", \"~/.vimrc\", \"~/.bashrc\", \"~/.zshrc\"]\n\
+                 [defaults]\non_duplicate = \"{}\"\n",
+                on_duplicate
+            ),
+        )
+        .unwrap();
+        fs::write(expand_path("~/repo/.vimrc"), "set number").unwrap();
+        fs::write(expand_path("~/repo/.bashrc"), "repo bashrc").unwrap();
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_init_creates_dotfolder_and_config() {
+        reset_test_environment();
+        let config = init("~/dotfiles", false).unwrap();
+
+        assert!(expand_path("~/dotfiles").is_dir());
+        assert!(config.config_file.exists());
+        let config = Config::new();
+        assert_eq!(config.dotfolder_path, "~/dotfiles");
+        assert_eq!(config.paths, vec!["~/.config/lazydot.toml"]);
+
+        let gitignore = fs::read_to_string(expand_path("~/dotfiles/.gitignore")).unwrap();
+        assert!(gitignore.lines().any(|line| line == "current_state.toml"));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_init_keeps_existing_config() {
+        reset_test_environment();
+        assert!(init(&get_home_dir().display().to_string(), false).is_err());
+        assert!(!Config::new().config_file.exists());
+
+        init("~/dotfiles", false).unwrap();
+        assert!(init("~/other", false).is_err());
+        assert!(!expand_path("~/other").exists());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_find_common_dotfiles() {
+        reset_test_environment();
+        let mut config = init("~/dotfiles", false).unwrap();
+        assert_eq!(find_common_dotfiles(&config), vec!["~/.bashrc"]);
+
+        config.add_path("~/.bashrc".to_string()).unwrap();
+        assert!(find_common_dotfiles(&config).is_empty());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_bootstrap_plan() {
+        reset_test_environment();
+        create_repo("ask");
+        link_repo_config("~/repo").unwrap();
+        assert!(is_link_to(
+            &Config::global_config_path(),
+            &expand_path("~/repo/.config/lazydot.toml")
+        ));
+
+        let plan = DotManager::new().plan();
+        assert_eq!(
+            plan,
+            vec![
+                ("~/.config/lazydot.toml".to_string(), PlannedAction::Linked),
+                ("~/.vimrc".to_string(), PlannedAction::Link),
+                ("~/.bashrc".to_string(), PlannedAction::Conflict),
+                ("~/.zshrc".to_string(), PlannedAction::Missing),
+            ]
+        );
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_bootstrap_links_and_syncs() {
+        reset_test_environment();
+        create_repo("overwritehome");
+        bootstrap("~/repo", true).unwrap();
+
+        for path in ["~/.vimrc", "~/.bashrc"] {
+            assert!(expand_path(path).is_symlink());
+        }
+        assert_eq!(
+            fs::read_to_string(expand_path("~/.bashrc")).unwrap(),
+            "repo bashrc"
+        );
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_bootstrap_rejects_wrong_location() {
+        reset_test_environment();
+        create_repo("ask");
+        fs::rename(expand_path("~/repo"), expand_path("~/elsewhere")).unwrap();
+        assert!(link_repo_config("~/elsewhere").is_err());
+        assert!(!Config::global_config_path().exists());
+    }
+}
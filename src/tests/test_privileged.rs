@@ -0,0 +1,150 @@
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::current_state::Outcome;
+    use crate::dot_manager::DotManager;
+    use crate::privileged::{PrivilegedRoot, Privileges};
+    use crate::utils::{expand_path, is_link_to, reset_test_environment};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// A fake root with an `/etc/hosts`, tracked by a config together with `~/.bashrc`.
+    fn setup_root(escalate: Option<&str>) -> PathBuf {
+        reset_test_environment();
+        let root = tempfile::tempdir().unwrap().keep();
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::write(root.join("etc/hosts"), "127.0.0.1 localhost").unwrap();
+
+        let escalate = escalate
+            .map(|command| format!("escalate = \"{}\"\n", command))
+            .unwrap_or_default();
+        let config_file = expand_path("~/.config/lazydot.toml");
+        fs::write(
+            &config_file,
+            format!(
+                "dotfolder_path = \"~/mydotfolder\"\npaths = []\n[defaults]\n\
+                 [[roots]]\nname = \"system\"\ntarget = \"{}\"\nstore = \"system/\"\n{}",
+                root.display(),
+                escalate
+            ),
+        )
+        .unwrap();
+        let mut config = Config::new();
+        config.add_path("~/.bashrc".to_string()).unwrap();
+        config
+            .add_path(root.join("etc/hosts").display().to_string())
+            .unwrap();
+        root
+    }
+
+    fn stored(path: &str) -> PathBuf {
+        expand_path("~/mydotfolder/system").join(path)
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_sync_root_paths() {
+        let root = setup_root(None);
+        let hosts = root.join("etc/hosts");
+        assert_eq!(
+            Config::new().tracked_paths(),
+            vec!["~/.bashrc".to_string(), hosts.display().to_string()]
+        );
+
+        DotManager::new().sync();
+        assert!(is_link_to(&hosts, &stored("etc/hosts")));
+        assert!(is_link_to(
+            &expand_path("~/.bashrc"),
+            &expand_path("~/mydotfolder/.bashrc")
+        ));
+
+        // Paths given in the store refer to their place in the root
+        fs::write(stored("etc/fstab"), "# fstab").unwrap();
+        let mut config = Config::new();
+        config
+            .add_path(stored("etc/fstab").display().to_string())
+            .unwrap();
+        assert!(
+            Config::new()
+                .tracked_paths()
+                .contains(&root.join("etc/fstab").display().to_string())
+        );
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_escalation_only_for_roots() {
+        let root = setup_root(Some("env"));
+        let hosts = root.join("etc/hosts");
+        let manager = DotManager::new();
+        manager.sync();
+        assert!(is_link_to(&hosts, &stored("etc/hosts")));
+
+        manager.undo();
+        assert!(!hosts.is_symlink());
+        assert_eq!(fs::read_to_string(&hosts).unwrap(), "127.0.0.1 localhost");
+
+        // A failing escalation only fails the root's paths
+        let root = setup_root(Some("false"));
+        let hosts = root.join("etc/hosts");
+        fs::create_dir_all(stored("etc")).unwrap();
+        fs::rename(&hosts, stored("etc/hosts")).unwrap();
+        DotManager::new().sync();
+        let state = DotManager::new().current_state;
+        assert_eq!(
+            state.get(&hosts.display().to_string()).unwrap().outcome,
+            Outcome::Failed
+        );
+        assert_eq!(state.get("~/.bashrc").unwrap().outcome, Outcome::Linked);
+    }
+
+    #[test]
+    fn test_escalated_copy_merges_folders() {
+        let root = tempfile::tempdir().unwrap().keep();
+        fs::create_dir_all(root.join("from/sub")).unwrap();
+        fs::write(root.join("from/sub/file"), "new").unwrap();
+        fs::create_dir_all(root.join("to")).unwrap();
+        fs::write(root.join("to/kept"), "kept").unwrap();
+        let privileges = Privileges {
+            roots: vec![PrivilegedRoot {
+                target: root.clone(),
+                command: "env".to_string(),
+            }],
+            except: vec![],
+        };
+
+        privileges
+            .copy(&root.join("from"), &root.join("to"))
+            .unwrap();
+        assert_eq!(fs::read_to_string(root.join("to/sub/file")).unwrap(), "new");
+        assert!(root.join("to/kept").exists());
+        assert!(!root.join("to/from").exists());
+    }
+
+    #[test]
+    fn test_command_for() {
+        let privileges = Privileges {
+            roots: vec![
+                PrivilegedRoot {
+                    target: PathBuf::from("/"),
+                    command: "sudo".to_string(),
+                },
+                PrivilegedRoot {
+                    target: PathBuf::from("/srv"),
+                    command: "doas".to_string(),
+                },
+            ],
+            except: vec![PathBuf::from("/home/user")],
+        };
+        assert_eq!(
+            privileges.command_for(Path::new("/etc/hosts")),
+            Some("sudo")
+        );
+        assert_eq!(privileges.command_for(Path::new("/srv/www")), Some("doas"));
+        assert_eq!(
+            privileges.command_for(Path::new("/home/user/.bashrc")),
+            None
+        );
+        assert_eq!(Privileges::default().command_for(Path::new("/etc")), None);
+    }
+}
@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod test {
+    use crate::config::{Config, DuplicateBehavior, LinkStyle, PathEntry};
+    use crate::current_state::CurrentState;
+    use crate::relocate::relocate;
+    use crate::utils::{
+        expand_path, get_home_and_dot_path, get_home_dir, get_link_style, is_link_to,
+        reset_test_environment, sync_config_with_manager,
+    };
+    use std::fs;
+
+    fn assert_all_linked(config: &Config) {
+        for path in config.tracked_paths() {
+            let (home, dot) = get_home_and_dot_path(&path);
+            assert!(
+                is_link_to(&home, &dot),
+                "Expected a link to the dotfolder: {}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_relocate_moves_dotfolder() {
+        reset_test_environment();
+        let mut config = Config::new();
+        config.paths.push(PathEntry {
+            path: "~/.config/lazydot.toml".to_string(),
+            link_style: Some(LinkStyle::Relative),
+            ..PathEntry::new(String::new())
+        });
+        config.save();
+        let manager = sync_config_with_manager(DuplicateBehavior::Ask);
+        let old_state_dir = CurrentState::dir(&manager.config);
+
+        relocate("~/dotfiles/new").unwrap();
+
+        assert!(!expand_path("~/mydotfolder").exists());
+        assert!(expand_path("~/dotfiles/new/.bashrc").exists());
+        let config = Config::new();
+        assert_eq!(config.dotfolder_path, "~/dotfiles/new");
+        assert_all_linked(&config);
+        assert_eq!(
+            get_link_style(&expand_path("~/.config/lazydot.toml")),
+            Some(LinkStyle::Relative)
+        );
+
+        // The state follows the dotfolder and records the new targets
+        assert!(!old_state_dir.exists());
+        let state = CurrentState::new(&config).unwrap();
+        let (_, dot) = get_home_and_dot_path("~/.bashrc");
+        assert_eq!(state.get("~/.bashrc").unwrap().target, Some(dot));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_relocate_with_config_in_dotfolder() {
+        reset_test_environment();
+        // As left by bootstrap: the config lives in the dotfolder, linked from home
+        let global = expand_path("~/.config/lazydot.toml");
+        let in_dotfolder = expand_path("~/mydotfolder/lazydot.toml");
+        Config::new().save();
+        fs::create_dir_all(expand_path("~/mydotfolder")).unwrap();
+        fs::rename(&global, &in_dotfolder).unwrap();
+        Config::link_global(&in_dotfolder, false).unwrap();
+        let mut config = Config::new();
+        config
+            .paths
+            .retain(|entry| entry.path != "~/.config/lazydot.toml");
+        config.save();
+        let manager = sync_config_with_manager(DuplicateBehavior::Ask);
+        let old_state_dir = CurrentState::dir(&manager.config);
+
+        relocate("~/dotfiles/new").unwrap();
+
+        assert!(is_link_to(
+            &global,
+            &expand_path("~/dotfiles/new/lazydot.toml")
+        ));
+        let config = Config::new();
+        assert_eq!(config.dotfolder_path, "~/dotfiles/new");
+        assert_all_linked(&config);
+        assert!(!old_state_dir.exists());
+        assert!(CurrentState::new(&config).is_ok());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_relocate_accepts_moved_dotfolder() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        fs::rename(expand_path("~/mydotfolder"), expand_path("~/moved")).unwrap();
+
+        // The linked config dangles until relocate fixes it
+        assert!(!expand_path("~/.config/lazydot.toml").exists());
+        relocate("~/moved").unwrap();
+        let config = Config::new();
+        assert_eq!(config.dotfolder_path, "~/moved");
+        assert_eq!(config.paths.len(), 4);
+        assert_all_linked(&config);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_relocate_outside_home() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        let outside = tempfile::tempdir().unwrap().keep().join("dotfiles");
+
+        relocate(outside.to_str().unwrap()).unwrap();
+        let config = Config::new();
+        assert_eq!(config.dotfolder_path, outside.display().to_string());
+        assert!(outside.join(".bashrc").is_file());
+        assert_all_linked(&config);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_relocate_rejects_invalid_targets() {
+        reset_test_environment();
+        sync_config_with_manager(DuplicateBehavior::Ask);
+        fs::create_dir(expand_path("~/taken")).unwrap();
+
+        assert!(relocate("~/mydotfolder").is_err());
+        assert!(relocate("~/mydotfolder/inner").is_err());
+        assert!(relocate("~/taken").unwrap_err().contains("already exists"));
+        assert!(relocate(&get_home_dir().display().to_string()).is_err());
+        assert_all_linked(&Config::new());
+    }
+}
@@ -2,11 +2,14 @@
 #[allow(dead_code)]
 #[allow(unused_imports)]
 pub(crate) mod test {
-    use crate::utils::{check_path, copy_all, delete, expand_path, get_home_dir};
+    use crate::utils::{
+        check_path, copy_all, delete, expand_env_vars, expand_path, get_home_dir, glob_match,
+        is_ignored, relative_to,
+    };
     use std::fs;
     use std::path::PathBuf;
-    use tempfile::tempdir;
     use tempfile::TempDir;
+    use tempfile::tempdir;
 
     pub fn create_file(path: &PathBuf, content: &str) {
         fs::create_dir_all(path.parent().unwrap()).unwrap();
@@ -59,6 +62,48 @@ pub(crate) mod test {
         assert_eq!(expanded, expected);
     }
 
+    #[test]
+    #[serial_test::serial]
+    fn test_expand_env_vars() {
+        let tmp_home = setup_env();
+        unsafe {
+            std::env::set_var("LAZYDOT_TEST_DIR", "/opt/dots");
+            std::env::remove_var("LAZYDOT_TEST_UNSET");
+        }
+        assert_eq!(
+            expand_env_vars("$LAZYDOT_TEST_DIR/a").unwrap(),
+            "/opt/dots/a"
+        );
+        assert_eq!(
+            expand_env_vars("${LAZYDOT_TEST_DIR}b").unwrap(),
+            "/opt/dotsb"
+        );
+        assert_eq!(expand_env_vars("~/a$").unwrap(), "~/a$");
+        assert!(expand_env_vars("$LAZYDOT_TEST_UNSET/a").is_err());
+        assert!(expand_env_vars("${LAZYDOT_TEST_DIR").is_err());
+        assert_eq!(
+            expand_path("${LAZYDOT_TEST_UNSET:-~/.config}/nvim"),
+            tmp_home.path().join(".config/nvim")
+        );
+        assert_eq!(
+            expand_path("${HOME}/.bashrc"),
+            get_home_dir().join(".bashrc")
+        );
+        // Defaults may nest variables
+        assert_eq!(
+            expand_env_vars("${LAZYDOT_TEST_UNSET:-${LAZYDOT_TEST_DIR}}/a").unwrap(),
+            "/opt/dots/a"
+        );
+        assert_eq!(
+            expand_env_vars("${LAZYDOT_TEST_UNSET:-${LAZYDOT_TEST_UNSET:-/b}/c}").unwrap(),
+            "/b/c"
+        );
+        assert!(expand_env_vars("${LAZYDOT_TEST_UNSET:-${LAZYDOT_TEST_DIR}").is_err());
+        unsafe {
+            std::env::remove_var("LAZYDOT_TEST_DIR");
+        }
+    }
+
     #[test]
     #[serial_test::serial]
     fn test_expand_path_relative() {
@@ -96,6 +141,34 @@ pub(crate) mod test {
         );
     }
 
+    #[test]
+    #[serial_test::serial]
+    fn test_relative_to_compares_real_paths() {
+        let tmp_home = setup_env();
+        let data = tempdir().unwrap();
+        create_file(&data.path().join("dotfiles/.vimrc"), "set number");
+        std::os::unix::fs::symlink(data.path(), tmp_home.path().join("data")).unwrap();
+        std::os::unix::fs::symlink(
+            data.path().join("dotfiles/.vimrc"),
+            tmp_home.path().join(".vimrc"),
+        )
+        .unwrap();
+
+        // A folder reached through a link in home is in both places
+        let through_link = expand_path("~/data/dotfiles/.vimrc");
+        assert_eq!(
+            relative_to(&through_link, &data.path().join("dotfiles")),
+            Some(PathBuf::from(".vimrc"))
+        );
+        assert_eq!(check_path("~/data/dotfiles").unwrap(), "~/data/dotfiles");
+
+        // A link itself isn't taken for its target
+        assert_eq!(
+            relative_to(&expand_path("~/.vimrc"), &data.path().join("dotfiles")),
+            None
+        );
+    }
+
     #[test]
     #[serial_test::serial]
     fn test_get_home_dir() {
@@ -169,4 +242,21 @@ pub(crate) mod test {
         assert!(copied_file.exists());
         assert_eq!(fs::read_to_string(copied_file).unwrap(), "nested data");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.md", "README.md"));
+        assert!(glob_match("fish_?ariables", "fish_variables"));
+        assert!(glob_match("*a*b", "xxaxxb"));
+        assert!(!glob_match("*.md", "README.txt"));
+        assert!(!glob_match("a?", "a"));
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        let patterns = vec!["*.md".to_string(), ".config/app*/cache".to_string()];
+        assert!(is_ignored(&patterns, &PathBuf::from("docs/README.md")));
+        assert!(is_ignored(&patterns, &PathBuf::from(".config/app1/cache")));
+        assert!(!is_ignored(&patterns, &PathBuf::from("cache")));
+    }
+}
@@ -1,10 +1,17 @@
-use crate::config::{Config, DuplicateBehavior};
+use crate::config::{Config, DuplicateBehavior, LinkStyle};
 use crate::dot_manager::DotManager;
 use std::io::ErrorKind;
-use std::path::PathBuf;
+use std::iter;
+use std::os::unix::fs::{PermissionsExt, symlink};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::RwLock;
+use std::time::SystemTime;
 use std::{env, fs};
 use tempfile::tempdir;
 
+static HOME_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
 pub fn check_path(path: &str) -> Result<String, String> {
     let input_path = expand_path(path);
 
@@ -12,26 +19,120 @@ pub fn check_path(path: &str) -> Result<String, String> {
         return Err(format!("path {} does not exist", path));
     }
 
-    let home = get_home_dir();
-
-    if input_path.eq(&home) {
+    let relative = relative_to(&input_path, &get_home_dir())
+        .ok_or(format!("path {} is not in the home directory", path))?;
+    if relative.as_os_str().is_empty() {
         return Err(format!("path {} is the home directory", path));
     }
-    if !input_path.starts_with(&home) {
-        return Err(format!("path {} is not in the home directory", path));
+
+    Ok(format!("~/{}", relative.display()))
+}
+
+/// `path` as written in the config: `~/...` inside home, absolute anywhere else.
+pub fn contract_path(path: &Path) -> String {
+    match relative_to(path, &get_home_dir()) {
+        Some(relative) if !relative.as_os_str().is_empty() => format!("~/{}", relative.display()),
+        _ => path.display().to_string(),
     }
+}
 
-    let relative = input_path
-        .strip_prefix(&home)
-        .map_err(|_| format!("path {} is not in the home directory", path))?;
+/// `path` with the symlinks in the folders leading to it resolved. The last component is kept
+/// as is, so a link in home isn't taken for its target.
+pub fn real_path(path: &Path) -> PathBuf {
+    let real = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent.canonicalize().map(|parent| parent.join(name)),
+        _ => path.canonicalize(),
+    };
+    real.unwrap_or(path.to_path_buf())
+}
 
-    Ok(format!("~/{}", relative.display()))
+/// `path` relative to the folder `base`, compared as written first and then as real paths.
+pub fn relative_to(path: &Path, base: &Path) -> Option<PathBuf> {
+    if let Ok(relative) = path.strip_prefix(base) {
+        return Some(relative.to_path_buf());
+    }
+    let base = base.canonicalize().ok()?;
+    real_path(path)
+        .strip_prefix(base)
+        .ok()
+        .map(Path::to_path_buf)
 }
 
+/// Expands `$VAR`, `${VAR}` and `${VAR:-default}` in `input`. `$HOME` is the home the
+/// dotfiles are deployed into, as `~` is. A `$` not followed by a name is kept as is.
+pub fn expand_env_vars(input: &str) -> Result<String, String> {
+    expand_env_vars_with_home(input, &get_home_dir())
+}
+
+/// `expand_env_vars` with `$HOME` standing for `home`.
+fn expand_env_vars_with_home(input: &str, home: &Path) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = input;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let (name, default, len) = if let Some(braced) = rest.strip_prefix('{') {
+            // The default may hold variables of its own: `${A:-${B}}`
+            let mut depth = 0;
+            let end = braced
+                .char_indices()
+                .find(|(_, c)| {
+                    match c {
+                        '{' => depth += 1,
+                        '}' if depth == 0 => return true,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    false
+                })
+                .map(|(end, _)| end)
+                .ok_or(format!("unclosed ${{ in \"{}\"", input))?;
+            match braced[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default), end + 2),
+                None => (&braced[..end], None, end + 2),
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (&rest[..end], None, end)
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            if len > 0 {
+                return Err(format!("invalid variable name in \"{}\"", input));
+            }
+            expanded.push('$');
+            continue;
+        }
+
+        let value = if name == "HOME" {
+            Some(home.display().to_string())
+        } else {
+            env::var(name).ok()
+        };
+        match (value, default) {
+            // `:-` also replaces an empty value, as in the shell
+            (Some(value), Some(default)) if value.is_empty() => {
+                expanded.push_str(&expand_env_vars_with_home(default, home)?)
+            }
+            (Some(value), _) => expanded.push_str(&value),
+            (None, Some(default)) => expanded.push_str(&expand_env_vars_with_home(default, home)?),
+            (None, None) => return Err(format!("${} is not defined", name)),
+        }
+        rest = &rest[len..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Expands `~/` and environment variables. Undefined variables are left as written,
+/// `Config` reports them when loading.
 pub fn expand_path(input: &str) -> PathBuf {
-    let mut path = if input.starts_with("~/") {
+    let input = expand_env_vars(input).unwrap_or(input.to_string());
+    let input = input.as_str();
+    let mut path = if let Some(relative) = input.strip_prefix("~/") {
         let home = get_home_dir();
-        home.join(&input[2..])
+        home.join(relative)
     } else {
         PathBuf::from(input)
     };
@@ -43,20 +144,293 @@ pub fn expand_path(input: &str) -> PathBuf {
     path
 }
 
+/// Expands `~/` and `$HOME` against the home of the user running lazydot, even with `--home`,
+/// for the config and the dotfolder.
+pub fn expand_user_path(input: &str) -> PathBuf {
+    let home = get_user_home_dir();
+    let input = expand_env_vars_with_home(input, &home).unwrap_or(input.to_string());
+    match input.strip_prefix("~/") {
+        Some(relative) => home.join(relative),
+        None => expand_path(&input),
+    }
+}
+
+/// Home the dotfiles are deployed into: the `--home` folder, or `$HOME`.
 pub fn get_home_dir() -> PathBuf {
-    PathBuf::from(get_home_dir_string())
+    HOME_DIR
+        .read()
+        .expect("Failed to read the home folder")
+        .clone()
+        .unwrap_or_else(get_user_home_dir)
 }
 
 pub fn get_home_dir_string() -> String {
-    env::var("HOME").expect("missing HOME environment variable")
+    get_home_dir().display().to_string()
+}
+
+/// `$HOME` of the user running lazydot, where its config and state are kept.
+pub fn get_user_home_dir() -> PathBuf {
+    PathBuf::from(env::var("HOME").expect("missing HOME environment variable"))
+}
+
+/// Deploys into `home` instead of `$HOME` for the rest of the run, as given by `--home`.
+pub fn set_home_dir(home: Option<PathBuf>) {
+    *HOME_DIR.write().expect("Failed to set the home folder") = home;
+}
+
+/// `$XDG_CONFIG_HOME`, or `~/.config` when it is unset.
+pub fn get_config_dir() -> PathBuf {
+    match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if PathBuf::from(&dir).is_absolute() => PathBuf::from(dir),
+        _ => get_user_home_dir().join(".config"),
+    }
 }
-pub fn delete(path: &PathBuf) {
+
+/// Folder where lazydot keeps machine-local data such as the current state and the undo
+/// journal: `$XDG_STATE_HOME/lazydot`, or `~/.local/state/lazydot` when it is unset.
+pub fn get_state_dir() -> PathBuf {
+    match env::var("XDG_STATE_HOME") {
+        Ok(dir) if PathBuf::from(&dir).is_absolute() => PathBuf::from(dir).join("lazydot"),
+        _ => get_user_home_dir().join(".local/state/lazydot"),
+    }
+}
+
+/// The name of this machine. macOS has neither file and doesn't export `$HOSTNAME`, there it
+/// comes from the `hostname` command.
+pub fn get_hostname() -> String {
+    let from_command = || {
+        Command::new("hostname")
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+    };
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .chain(env::var("HOSTNAME").ok())
+        .chain(iter::once_with(from_command).flatten())
+        .map(|hostname| hostname.trim().to_string())
+        .find(|hostname| !hostname.is_empty())
+        .unwrap_or("localhost".to_string())
+}
+
+/// True when an executable named `command` is in one of the folders of `$PATH`.
+pub fn has_command(command: &str) -> bool {
+    env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths).any(|dir| {
+            fs::metadata(dir.join(command)).is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        })
+    })
+}
+
+/// Entries of the dotfolder that are never treated as dotfiles.
+const BUILTIN_IGNORES: [&str; 4] = [".git", ".gitignore", ".lazydotignore", "current_state.toml"];
+
+/// Ignore patterns for the dotfolder: the built-in ones plus the lines of its `.lazydotignore`.
+pub fn load_ignore_patterns(dotfolder: &Path) -> Vec<String> {
+    let content = fs::read_to_string(dotfolder.join(".lazydotignore")).unwrap_or_default();
+    BUILTIN_IGNORES
+        .iter()
+        .map(|pattern| pattern.to_string())
+        .chain(
+            content
+                .lines()
+                .map(|line| line.trim().trim_end_matches('/'))
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.to_string()),
+        )
+        .collect()
+}
+
+/// Patterns containing `/` match the whole relative path, others match the file name at any depth.
+pub fn is_ignored(patterns: &[String], relative: &Path) -> bool {
+    let relative_str = relative.to_string_lossy();
+    let name = relative
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    patterns.iter().any(|pattern| {
+        if pattern.contains('/') {
+            glob_match(pattern.trim_start_matches('/'), &relative_str)
+        } else {
+            glob_match(pattern, &name)
+        }
+    })
+}
+
+/// Matches `text` against a glob supporting `*` and `?`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// True for a folder that isn't a symlink to one.
+pub fn is_real_dir(path: &Path) -> bool {
+    path.is_dir() && !path.is_symlink()
+}
+
+/// `path` with `.bak` appended to its name, numbered when that name is taken:
+/// `a.toml.bak`, then `a.toml.bak.1`, ...
+pub fn backup_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .expect("Failed to get the file name")
+        .to_string_lossy();
+    let mut backup = path.with_file_name(format!("{}.bak", name));
+    let mut count = 1;
+    while backup.exists() || backup.is_symlink() {
+        backup = path.with_file_name(format!("{}.bak.{}", name, count));
+        count += 1;
+    }
+    backup
+}
+
+/// Files below `dir`, relative to it and sorted. Symlinks count as files, and nothing below
+/// a folder `ignored` returns true for is listed.
+pub fn list_files(dir: &Path, ignored: &dyn Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    collect_files(dir, Path::new(""), ignored, &mut files);
+    files.sort();
+    files
+}
+
+fn collect_files(
+    dir: &Path,
+    relative: &Path,
+    ignored: &dyn Fn(&Path) -> bool,
+    files: &mut Vec<PathBuf>,
+) {
+    let Ok(entries) = fs::read_dir(dir.join(relative)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let child = relative.join(entry.file_name());
+        if ignored(&child) {
+            continue;
+        }
+        if is_real_dir(&dir.join(&child)) {
+            collect_files(dir, &child, ignored, files);
+        } else {
+            files.push(child);
+        }
+    }
+}
+
+/// When `path` was last modified, the latest of its entries for a folder. Links are not followed.
+pub fn last_modified(path: &Path) -> Option<SystemTime> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    let modified = metadata.modified().ok();
+    if !metadata.is_dir() {
+        return modified;
+    }
+    fs::read_dir(path)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| last_modified(&entry.path()))
+        .chain(modified)
+        .max()
+}
+
+/// True when both paths hold the same bytes, folders compared entry by entry.
+pub fn same_content(a: &Path, b: &Path) -> bool {
+    let (Ok(meta_a), Ok(meta_b)) = (fs::symlink_metadata(a), fs::symlink_metadata(b)) else {
+        return false;
+    };
+    if meta_a.is_symlink() || meta_b.is_symlink() {
+        return meta_a.is_symlink()
+            && meta_b.is_symlink()
+            && fs::read_link(a).ok() == fs::read_link(b).ok();
+    }
+    if meta_a.is_dir() && meta_b.is_dir() {
+        let names = |dir: &Path| -> Option<Vec<_>> {
+            let mut names: Vec<_> = fs::read_dir(dir)
+                .ok()?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<Result<_, _>>()
+                .ok()?;
+            names.sort();
+            Some(names)
+        };
+        return match (names(a), names(b)) {
+            (Some(names_a), Some(names_b)) => {
+                names_a == names_b
+                    && names_a
+                        .iter()
+                        .all(|name| same_content(&a.join(name), &b.join(name)))
+            }
+            _ => false,
+        };
+    }
+    meta_a.is_file()
+        && meta_b.is_file()
+        && meta_a.len() == meta_b.len()
+        && matches!((fs::read(a), fs::read(b)), (Ok(x), Ok(y)) if x == y)
+}
+
+/// Stable content hash (64-bit FNV-1a, hex) of a file, or of a folder's relative paths and
+/// file contents. `None` when the path doesn't exist.
+pub fn hash_path(path: &Path) -> Option<String> {
+    fn feed(hash: &mut u64, bytes: &[u8]) {
+        for byte in bytes {
+            *hash ^= *byte as u64;
+            *hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    fn walk(hash: &mut u64, root: &Path, path: &Path) -> std::io::Result<()> {
+        if path.is_dir() {
+            let mut entries: Vec<_> = fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<_, _>>()?;
+            entries.sort();
+            for entry in entries {
+                let relative = entry.strip_prefix(root).unwrap_or(&entry);
+                feed(hash, relative.to_string_lossy().as_bytes());
+                walk(hash, root, &entry)?;
+            }
+        } else if path.is_file() {
+            feed(hash, &fs::read(path)?);
+        }
+        Ok(())
+    }
+
+    if !path.exists() {
+        return None;
+    }
+    let mut hash = 0xcbf29ce484222325;
+    walk(&mut hash, path, path).ok()?;
+    Some(format!("{:016x}", hash))
+}
+
+pub fn delete(path: &Path) {
     if path.is_file() || path.is_symlink() {
-        fs::remove_file(path).expect(&format!("Failed to delete {}", path.display()));
+        fs::remove_file(path).unwrap_or_else(|_| panic!("Failed to delete {}", path.display()));
     }
     // Check if it's a directory and remove the directory recursively
     else if path.is_dir() {
-        fs::remove_dir_all(path).expect(&format!("Failed to delete {}", path.display()));
+        fs::remove_dir_all(path).unwrap_or_else(|_| panic!("Failed to delete {}", path.display()));
     }
     // If it's neither a symlink, file, nor directory
     else {
@@ -67,7 +441,7 @@ pub fn delete(path: &PathBuf) {
     }
 }
 
-pub fn copy_all(source_path: &PathBuf, target_path: &PathBuf) -> Result<(), std::io::Error> {
+pub fn copy_all(source_path: &Path, target_path: &Path) -> Result<(), std::io::Error> {
     if !source_path.exists() {
         return Err(std::io::Error::new(
             ErrorKind::NotFound,
@@ -75,17 +449,11 @@ pub fn copy_all(source_path: &PathBuf, target_path: &PathBuf) -> Result<(), std:
         ));
     }
     if source_path.is_file() {
-        let parent = target_path.parent().expect(&format!(
-            "Failed to get parent of {}",
-            target_path.display()
-        ));
-        fs::create_dir_all(parent)
-            .expect(&format!("Failed to create directory {}", parent.display()));
-        fs::copy(source_path, target_path).expect(&format!(
-            "Failed to copy {} to {}",
-            source_path.display(),
-            target_path.display()
-        ));
+        let parent = target_path
+            .parent()
+            .unwrap_or_else(|| panic!("Failed to get parent of {}", target_path.display()));
+        fs::create_dir_all(parent)?;
+        fs::copy(source_path, target_path)?;
         return Ok(());
     }
     if source_path.is_dir() {
@@ -94,35 +462,37 @@ pub fn copy_all(source_path: &PathBuf, target_path: &PathBuf) -> Result<(), std:
             let entry_path = entry.path();
 
             // Compute a relative path from the source root
-            let relative = entry_path.strip_prefix(source_path).expect(&format!(
-                "Failed to strip prefix from {}",
-                entry_path.display()
-            ));
+            let relative = entry_path
+                .strip_prefix(source_path)
+                .unwrap_or_else(|_| panic!("Failed to strip prefix from {}", entry_path.display()));
 
             let nested_target = target_path.join(relative);
+            // Links inside are kept as links, as `cp -a` does, dangling ones included
+            if entry_path.is_symlink() {
+                if nested_target.is_symlink() || nested_target.is_file() {
+                    fs::remove_file(&nested_target)?;
+                }
+                fs::create_dir_all(target_path)?;
+                symlink(fs::read_link(&entry_path)?, &nested_target)?;
+                continue;
+            }
             copy_all(&entry_path, &nested_target)?;
         }
     } else {
-        return Err(std::io::Error::new(
-            ErrorKind::Other,
-            format!(
-                "Failed to copy {} is not a file or directory",
-                source_path.display()
-            ),
-        ));
+        return Err(std::io::Error::other(format!(
+            "Failed to copy {} is not a file or directory",
+            source_path.display()
+        )));
     }
     Ok(())
 }
 
-fn get_relative_path(path: &String) -> Result<PathBuf, String> {
+fn get_relative_path(path: &str) -> Result<PathBuf, String> {
     // Expand ~ or $HOME to an absolute path
     let path_in_home = expand_path(path);
 
-    let relative_path = path_in_home
-        .strip_prefix(get_home_dir())
-        .expect("Failed to strip prefix from home dir")
-        .to_path_buf();
-    Ok(relative_path)
+    relative_to(&path_in_home, &get_home_dir())
+        .ok_or(format!("path {} is not in the home directory", path))
 }
 
 /// Resets test environment by:
@@ -147,7 +517,7 @@ pub fn reset_test_environment() {
     env::set_current_dir(&root).expect("Failed to set current dir");
 
     // Create a new temporary home directory
-    let temp_home_path = tempdir().expect("Failed to create temp dir").into_path();
+    let temp_home_path = tempdir().expect("Failed to create temp dir").keep();
 
     // Set HOME to the new fake temp dir
     unsafe {
@@ -155,7 +525,11 @@ pub fn reset_test_environment() {
             "HOME",
             temp_home_path.to_str().expect("Invalid UTF-8 in temp home"),
         );
+        env::remove_var("LAZYDOT_CONFIG");
+        env::remove_var("XDG_CONFIG_HOME");
+        env::remove_var("XDG_STATE_HOME");
     }
+    set_home_dir(None);
 
     // Copy fake home structure into temp HOME
     let fake_env_path = PathBuf::from("src/tests/Data/fake_env");
@@ -200,15 +574,137 @@ pub fn sync_config_with_manager(duplicate_behavior: DuplicateBehavior) -> DotMan
     manager
 }
 
-pub fn get_home_and_dot_path(path: &String) -> (PathBuf, PathBuf) {
+/// Target to store in the symlink at `path_in_home` so it points to `path_in_dotfolder`.
+pub fn get_link_target(path_in_dotfolder: &Path, path_in_home: &Path, style: LinkStyle) -> PathBuf {
+    match style {
+        LinkStyle::Absolute => path_in_dotfolder.to_path_buf(),
+        LinkStyle::Relative => {
+            // Relative targets are resolved from the real folder holding the link
+            let from = path_in_home
+                .parent()
+                .map(|parent| parent.canonicalize().unwrap_or(parent.to_path_buf()))
+                .expect("Failed to get parent of home path");
+            let to = path_in_dotfolder
+                .canonicalize()
+                .unwrap_or(path_in_dotfolder.to_path_buf());
+            get_relative_link(&from, &to)
+        }
+    }
+}
+
+/// Path of `to` relative to the folder `from`, both absolute.
+fn get_relative_link(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component);
+    }
+    relative
+}
+
+/// True when `path_in_home` is a symlink resolving to `path_in_dotfolder`, whatever its style.
+pub fn is_link_to(path_in_home: &Path, path_in_dotfolder: &Path) -> bool {
+    if !path_in_home.is_symlink() {
+        return false;
+    }
+    match (
+        path_in_home.canonicalize(),
+        path_in_dotfolder.canonicalize(),
+    ) {
+        (Ok(target), Ok(dot)) => target == dot,
+        _ => false,
+    }
+}
+
+/// Where the symlink at `path` points to, even when dangling. Relative targets are resolved
+/// against the link's folder, `..` components are removed lexically.
+pub fn resolve_link(path: &Path) -> Option<PathBuf> {
+    let target = fs::read_link(path).ok()?;
+    let target = if target.is_relative() {
+        let parent = path.parent()?;
+        parent
+            .canonicalize()
+            .unwrap_or(parent.to_path_buf())
+            .join(target)
+    } else {
+        target
+    };
+    let mut resolved = PathBuf::new();
+    for component in target.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                resolved.pop();
+            }
+            std::path::Component::CurDir => {}
+            component => resolved.push(component),
+        }
+    }
+    Some(resolved)
+}
+
+/// Symlinks under `roots`, at most `depth` folders deep, that point into `dotfolder`,
+/// dangling or not. Neither the dotfolder nor symlinked folders are walked into.
+pub fn find_links_into(dotfolder: &Path, roots: &[PathBuf], depth: usize) -> Vec<PathBuf> {
+    fn walk(dir: &Path, dotfolders: &[PathBuf], depth: usize, links: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_symlink() {
+                if resolve_link(&path)
+                    .is_some_and(|target| dotfolders.iter().any(|dot| target.starts_with(dot)))
+                {
+                    links.push(path);
+                }
+            } else if path.is_dir() && depth > 0 && !dotfolders.contains(&path) {
+                walk(&path, dotfolders, depth - 1, links);
+            }
+        }
+    }
+
+    let mut dotfolders = vec![dotfolder.to_path_buf()];
+    if let Ok(real_dotfolder) = dotfolder.canonicalize() {
+        dotfolders.push(real_dotfolder);
+    }
+    let mut links = Vec::new();
+    for root in roots {
+        walk(root, &dotfolders, depth, &mut links);
+    }
+    links.sort();
+    links.dedup();
+    links
+}
+
+/// Style of the existing symlink at `path`.
+pub fn get_link_style(path: &Path) -> Option<LinkStyle> {
+    let target = fs::read_link(path).ok()?;
+    if target.is_absolute() {
+        Some(LinkStyle::Absolute)
+    } else {
+        Some(LinkStyle::Relative)
+    }
+}
+
+pub fn get_home_and_dot_path(path: &str) -> (PathBuf, PathBuf) {
     let home = expand_path(path);
     let dot = get_path_in_dotfolder(&home).expect("failed to get path inside the dotfolder");
     (home, dot)
 }
 
-pub fn get_path_in_dotfolder(path_in_home: &PathBuf) -> Result<PathBuf, String> {
+pub fn get_path_in_dotfolder(path_in_home: &Path) -> Result<PathBuf, String> {
     let config = Config::new();
-    let relative_path = get_relative_path(&path_in_home.to_str().unwrap().to_string())?;
-    let path_in_dotfolder = expand_path(&config.dotfolder_path).join(&relative_path);
-    Ok(path_in_dotfolder)
+    let dotfolder = expand_user_path(&config.dotfolder_path);
+    // Paths of a root are kept under its store
+    if let Some((root, relative)) = config.root_for(path_in_home) {
+        return Ok(dotfolder.join(&root.store).join(relative));
+    }
+    let relative_path = get_relative_path(path_in_home.to_str().unwrap())?;
+    Ok(dotfolder.join(&relative_path))
 }